@@ -0,0 +1,28 @@
+//! Decodes raw HID reports into normalized joystick states, no device needed
+//!
+//!     cargo run --example decode
+
+use rattacker::dispatch::State;
+use rattacker::raw_input::Packet;
+
+fn main() -> Result<(), String> {
+    // x, y, z, then button bits: trigger is the lowest bit of the 4th byte
+    let reports: [[u8; 5]; 3] = [
+        [0x80, 0x80, 0x80, 0x00, 0x00],
+        [0xff, 0x80, 0x80, 0x01, 0x00],
+        [0x80, 0x00, 0x00, 0x00, 0x04],
+    ];
+
+    // The first report is the stick at rest
+    let zero = Packet::parse(&reports[0])?;
+    let zero = [zero.x_axis, zero.y_axis];
+
+    for report in reports.iter() {
+        let packet = Packet::parse(report)?;
+        let state = State::from_packet(zero, packet);
+        println!("{:02x?} => x {:+.2} y {:+.2} z {:+.2}, buttons {:?}",
+            report, state.x_axis(), state.y_axis(), state.z_axis(), state.buttons());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+//! Prints the events of a connected Attack3 until interrupted
+//!
+//!     cargo run --example events
+
+use std::error::Error;
+
+use hidapi::HidApi;
+use rattacker::device::{Joystick, ATTACK3};
+use rattacker::events::Events;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let hidapi = HidApi::new()?;
+    let mut joystick = Joystick::open(&hidapi, ATTACK3)?;
+
+    // Zones are measured against these deadzones
+    let mut events = Events::new((0.2, 0.2));
+    loop {
+        for report in joystick.read()? {
+            for event in events.next(&report.state) {
+                println!("{}", event);
+            }
+        }
+    }
+}
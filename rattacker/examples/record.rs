@@ -0,0 +1,48 @@
+//! Feeds made-up states through a preset and prints what it would send
+//!
+//!     cargo run --example record
+
+use std::error::Error;
+use std::rc::Rc;
+
+use rattacker::config::ProfileSet;
+use rattacker::dispatch::{Manager, State};
+use rattacker::presets::Preset;
+use rattacker::raw_input::Packet;
+use rattacker::record::RecordDispatch;
+
+fn state(x: u8, y: u8, trigger: bool) -> State {
+    let mut buttons = [false; 11];
+    buttons[0] = trigger;
+    State::from_packet([0x80, 0x80], Packet {
+        buttons,
+        x_axis: x,
+        y_axis: y,
+        z_axis: 0x80,
+    })
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let preset = Preset::find("wasd")?;
+    let mut profiles = ProfileSet::from_value(preset.value()?)?;
+    let config = profiles.profiles.remove(0).config;
+
+    let recorder = Rc::new(RecordDispatch::new());
+    let mut manager = Manager::with_dispatcher(config, recorder.clone());
+    manager.subscribe(|event| println!("{}", event));
+
+    let states = [
+        state(0x80, 0x80, false),
+        state(0x80, 0x00, false),
+        state(0xff, 0x00, true),
+        state(0x80, 0x80, false),
+    ];
+    for s in states.iter() {
+        manager.step(s.clone());
+        for (_, action) in recorder.take() {
+            println!("  {}", action);
+        }
+    }
+
+    Ok(())
+}
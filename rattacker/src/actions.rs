@@ -0,0 +1,409 @@
+use std::rc::Rc;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use super::dispatch::{Dispatcher, WHEEL_DELTA};
+use super::config::{AxisKeyConfig, DirectionsConfig, Key, PwmConfig, ScrollConfig, TurboConfig, Wheel};
+
+/// Shortest period any repeating action runs at, rates past 1000/s are capped to it
+const MIN_PERIOD: Duration = Duration::from_millis(1);
+
+/// Period of secs, no shorter than MIN_PERIOD
+fn period(secs: f32) -> Duration {
+    Duration::from_secs_f32(secs).max(MIN_PERIOD)
+}
+
+/// Auto-fire state for a single button
+/// The key is pressed at the start of every period and released once the
+///   duty cycle has elapsed
+pub struct Turbo<T: Dispatcher> {
+    dispatcher: Rc<T>,
+    key: Key,
+    period: Duration,
+    on_time: Duration,
+
+    held_since: Option<Instant>,
+    key_down: bool,
+}
+
+impl<T: Dispatcher> Turbo<T> {
+    pub fn new(dispatcher: Rc<T>, cfg: TurboConfig) -> Self {
+        assert!(cfg.rate > 0.0, "Turbo rate must be positive: {}", cfg.rate);
+
+        let period = period(1.0 / cfg.rate);
+        let on_time = period.mul_f32(cfg.duty_cycle.clamp(0.0, 1.0));
+
+        Turbo {
+            dispatcher,
+            key: cfg.key,
+            period,
+            on_time,
+
+            held_since: None,
+            key_down: false,
+        }
+    }
+
+    pub fn press(&mut self, now: Instant) {
+        self.held_since = Some(now);
+        self.tick(now);
+    }
+
+    /// Stops auto-fire, leaving the key released
+    pub fn release(&mut self) {
+        self.held_since = None;
+        if self.key_down {
+            self.dispatcher.key_up(self.key);
+            self.key_down = false;
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        let held_since = match self.held_since {
+            Some(held_since) => held_since,
+            None => return,
+        };
+
+        let elapsed = now.duration_since(held_since).as_nanos();
+        let phase = elapsed % self.period.as_nanos();
+        let should_be_down = phase < self.on_time.as_nanos();
+
+        if should_be_down && !self.key_down {
+            self.dispatcher.key_down(self.key);
+            self.key_down = true;
+        } else if !should_be_down && self.key_down {
+            self.dispatcher.key_up(self.key);
+            self.key_down = false;
+        }
+    }
+}
+
+/// Latching state for a single button
+/// Every press flips the key between held and released
+pub struct Toggle<T: Dispatcher> {
+    dispatcher: Rc<T>,
+    key: Key,
+    latched: Rc<Cell<bool>>,
+}
+
+impl<T: Dispatcher> Toggle<T> {
+    pub fn new(dispatcher: Rc<T>, key: Key) -> Self {
+        Toggle {
+            dispatcher,
+            key,
+            latched: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Shared view of the latch for status output
+    pub fn latched(&self) -> Rc<Cell<bool>> {
+        self.latched.clone()
+    }
+
+    pub fn press(&self) {
+        if self.latched.get() {
+            self.dispatcher.key_up(self.key);
+            self.latched.set(false);
+        } else {
+            self.dispatcher.key_down(self.key);
+            self.latched.set(true);
+        }
+    }
+
+    /// Releases the key if it is currently latched
+    pub fn release(&self) {
+        if self.latched.get() {
+            self.dispatcher.key_up(self.key);
+            self.latched.set(false);
+        }
+    }
+}
+
+/// Wheel turns for a single button
+/// One on every press, then `repeat` per second while held
+pub struct Scroll<T: Dispatcher> {
+    dispatcher: Rc<T>,
+    wheel: Wheel,
+    delta: i32,
+    period: Option<Duration>,
+
+    next: Option<Instant>,
+}
+
+impl<T: Dispatcher> Scroll<T> {
+    pub fn new(dispatcher: Rc<T>, cfg: ScrollConfig) -> Self {
+        Scroll {
+            dispatcher,
+            wheel: cfg.wheel,
+            delta: (cfg.notches * WHEEL_DELTA as f32).round() as i32,
+            period: cfg.repeat.map(|rate| period(1.0 / rate)),
+
+            next: None,
+        }
+    }
+
+    pub fn press(&mut self, now: Instant) {
+        self.dispatcher.scroll(self.wheel, self.delta);
+        self.next = self.period.map(|period| now + period);
+    }
+
+    pub fn release(&mut self) {
+        self.next = None;
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        let period = match self.period {
+            Some(period) => period,
+            None => return,
+        };
+
+        // One scroll per elapsed period, even if ticks were late
+        while let Some(next) = self.next {
+            if next > now {
+                break;
+            }
+            self.dispatcher.scroll(self.wheel, self.delta);
+            self.next = Some(next + period);
+        }
+    }
+}
+
+/// Pulse-width modulation of one stick axis's keys
+/// The key for the side the axis is on is held for a part of every period
+///   proportional to how far the axis is past its deadzone
+pub struct Pwm<T: Dispatcher> {
+    dispatcher: Rc<T>,
+    positive: Key,
+    negative: Key,
+    deadzone: f32,
+    period: Duration,
+
+    /// Start of the first period, reset whenever the axis goes back to its deadzone
+    since: Option<Instant>,
+    key_down: Option<Key>,
+}
+
+impl<T: Dispatcher> Pwm<T> {
+    pub fn new(dispatcher: Rc<T>, axis: &AxisKeyConfig, cfg: PwmConfig) -> Self {
+        assert!(cfg.period > 0.0, "PWM period must be positive: {}", cfg.period);
+
+        Pwm {
+            dispatcher,
+            positive: axis.positive,
+            negative: axis.negative,
+            deadzone: axis.deadzone,
+            period: period(cfg.period),
+
+            since: None,
+            key_down: None,
+        }
+    }
+
+    /// Presses or releases for the axis at value f, call on every step
+    pub fn update(&mut self, f: f32, now: Instant) {
+        if f.abs() <= self.deadzone {
+            self.stop();
+            return;
+        }
+
+        let key = if f > 0.0 { self.positive } else { self.negative };
+        if matches!(self.key_down, Some(down) if down != key) {
+            // Crossed the center without a step inside the deadzone
+            self.stop();
+        }
+
+        let since = *self.since.get_or_insert(now);
+        let duty_cycle = ((f.abs() - self.deadzone) / (1.0 - self.deadzone)).min(1.0);
+        let elapsed = now.duration_since(since).as_nanos();
+        let phase = elapsed % self.period.as_nanos();
+        let should_be_down = duty_cycle >= 1.0
+            || phase < self.period.mul_f32(duty_cycle).as_nanos();
+
+        if should_be_down && self.key_down.is_none() {
+            self.dispatcher.key_down(key);
+            self.key_down = Some(key);
+        } else if !should_be_down && self.key_down.is_some() {
+            self.dispatcher.key_up(key);
+            self.key_down = None;
+        }
+    }
+
+    /// Stops pulsing, leaving the keys released
+    pub fn stop(&mut self) {
+        self.since = None;
+        if let Some(key) = self.key_down.take() {
+            self.dispatcher.key_up(key);
+        }
+    }
+}
+
+/// Multi-stage keys of one stick axis
+/// Holds the keys of the furthest stage the axis is past, pressing and
+///   releasing only the keys that differ from what is held
+pub struct Stages<T: Dispatcher> {
+    dispatcher: Rc<T>,
+    /// (threshold, keys) by increasing threshold, the first at the deadzone
+    positive: Vec<(f32, Vec<Key>)>,
+    negative: Vec<(f32, Vec<Key>)>,
+
+    held: Vec<Key>,
+}
+
+impl<T: Dispatcher> Stages<T> {
+    pub fn new(dispatcher: Rc<T>, axis: &AxisKeyConfig) -> Self {
+        let mut positive = vec![(axis.deadzone, vec![axis.positive])];
+        let mut negative = vec![(axis.deadzone, vec![axis.negative])];
+        for stage in axis.stages.iter() {
+            let below = positive[positive.len() - 1].1.clone();
+            positive.push((stage.threshold, stage.positive.clone().unwrap_or(below)));
+
+            let below = negative[negative.len() - 1].1.clone();
+            negative.push((stage.threshold, stage.negative.clone().unwrap_or(below)));
+        }
+
+        Stages {
+            dispatcher,
+            positive,
+            negative,
+
+            held: Vec::new(),
+        }
+    }
+
+    /// Holds the keys for the axis at value f, call on every step
+    pub fn update(&mut self, f: f32) {
+        let stages = if f > 0.0 { &self.positive } else { &self.negative };
+        let keys = stages.iter()
+            .rev()
+            .find(|(threshold, _)| f.abs() > *threshold)
+            .map(|(_, keys)| keys.clone())
+            .unwrap_or_default();
+        self.hold(keys);
+    }
+
+    /// Releases every key of every stage
+    pub fn stop(&mut self) {
+        self.hold(Vec::new());
+    }
+
+    fn hold(&mut self, keys: Vec<Key>) {
+        hold(&*self.dispatcher, &mut self.held, keys);
+    }
+}
+
+/// Keys held by the direction the stick points in
+pub struct Directions<T: Dispatcher> {
+    dispatcher: Rc<T>,
+    /// (center in degrees clockwise from up, keys)
+    sectors: Vec<(f32, Vec<Key>)>,
+    /// Degrees from a sector's center that it still holds
+    reach: f32,
+    deadzone: f32,
+
+    held: Vec<Key>,
+}
+
+impl<T: Dispatcher> Directions<T> {
+    pub fn new(dispatcher: Rc<T>, cfg: &DirectionsConfig) -> Self {
+        Directions {
+            dispatcher,
+            sectors: cfg.keys.iter()
+                .map(|(direction, keys)| (direction.angle(), keys.clone()))
+                .collect(),
+            reach: cfg.sector_width() / 2.0 + cfg.overlap,
+            deadzone: cfg.deadzone,
+
+            held: Vec::new(),
+        }
+    }
+
+    /// Holds the keys for the stick at (x, y), call on every step
+    pub fn update(&mut self, x: f32, y: f32) {
+        let mut keys = Vec::new();
+        if x.hypot(y) > self.deadzone {
+            let angle = x.atan2(y).to_degrees();
+            for (center, sector_keys) in self.sectors.iter() {
+                // Shortest way around the circle, in range [0, 180]
+                let off = (angle - center).rem_euclid(360.0);
+                if off.min(360.0 - off) <= self.reach {
+                    for k in sector_keys.iter() {
+                        if !keys.contains(k) {
+                            keys.push(*k);
+                        }
+                    }
+                }
+            }
+        }
+        hold(&*self.dispatcher, &mut self.held, keys);
+    }
+
+    /// Releases every direction's keys
+    pub fn stop(&mut self) {
+        hold(&*self.dispatcher, &mut self.held, Vec::new());
+    }
+}
+
+/// Changes held into keys, pressing and releasing only the keys that differ
+fn hold<T: Dispatcher>(dispatcher: &T, held: &mut Vec<Key>, keys: Vec<Key>) {
+    for k in held.iter().rev() {
+        if !keys.contains(k) {
+            dispatcher.key_up(*k);
+        }
+    }
+    for k in keys.iter() {
+        if !held.contains(k) {
+            dispatcher.key_down(*k);
+        }
+    }
+    *held = keys;
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::dispatch::Action;
+    use crate::record::RecordDispatch;
+
+    #[test]
+    fn turbo_rate_past_min_period() {
+        let recorder = Rc::new(RecordDispatch::new());
+        let cfg = serde_json::from_value(json!({ "key": "E", "rate": 1e12, "duty_cycle": 0.5 })).unwrap();
+        let mut turbo = Turbo::new(recorder.clone(), cfg);
+
+        let start = Instant::now();
+        turbo.press(start);
+        turbo.tick(start + Duration::from_micros(700));
+        // Past the on time of a 1ms period
+        let actions: Vec<Action> = recorder.take().into_iter().map(|(_, a)| a).collect();
+        assert_eq!(actions, vec![Action::KeyDown(Key::E), Action::KeyUp(Key::E)]);
+    }
+
+    #[test]
+    fn scroll_repeat_past_min_period() {
+        let recorder = Rc::new(RecordDispatch::new());
+        let cfg = serde_json::from_value(json!({ "wheel": "Vertical", "notches": 1.0, "repeat": 1e12 })).unwrap();
+        let mut scroll = Scroll::new(recorder.clone(), cfg);
+
+        let start = Instant::now();
+        scroll.press(start);
+        scroll.tick(start + Duration::from_micros(2500));
+        // The press and one per 1ms period
+        assert_eq!(recorder.take().len(), 3);
+    }
+
+    #[test]
+    fn pwm_period_past_min_period() {
+        let recorder = Rc::new(RecordDispatch::new());
+        let axis: AxisKeyConfig = serde_json::from_value(json!({
+            "positive": "D", "negative": "A", "deadzone": 0.1, "pwm": { "period": 1e-12 }
+        })).unwrap();
+        let mut pwm = Pwm::new(recorder.clone(), &axis, axis.pwm.unwrap());
+
+        let start = Instant::now();
+        pwm.update(0.5, start);
+        pwm.update(0.5, start + Duration::from_micros(1500));
+        assert_eq!(recorder.take()[0].1, Action::KeyDown(Key::D));
+    }
+}
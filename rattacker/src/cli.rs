@@ -0,0 +1,406 @@
+use std::env;
+use std::ffi::OsString;
+use std::process;
+
+use serde_json::Value;
+use structopt::StructOpt;
+use structopt::clap::{AppSettings, ErrorKind};
+
+use rattacker::config::*;
+use rattacker::device::DeviceId;
+
+const AFTER_HELP: &str = "\
+A bare config path runs it, e.g. `rattacker joy-config/linear.json`
+
+EXIT CODES:
+    0    Success
+    1    Error, e.g. an invalid config or a lost device
+    2    Invalid command line";
+
+/// Kept in sync with Command, for the bare config path shorthand
+const SUBCOMMANDS: [&str; 11] = [
+    "run",
+    "list-devices",
+    "monitor",
+    "calibrate",
+    "validate",
+    "convert",
+    "migrate",
+    "resolve",
+    "schema",
+    "preset",
+    "help",
+];
+
+/// Options of run and validate that take a value, which is not a bare config path
+const VALUE_OPTIONS: [&str; 11] = [
+    "-c",
+    "--config",
+    "-p",
+    "--preset",
+    "-d",
+    "--device",
+    "--mode",
+    "--deadzone",
+    "--x-deadzone",
+    "--y-deadzone",
+    "--set",
+];
+
+/// Translates Logitech Attack3 joystick input into keyboard and mouse input
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "rattacker",
+    after_help = AFTER_HELP,
+    global_settings = &[AppSettings::VersionlessSubcommands, AppSettings::DeriveDisplayOrder],
+)]
+pub enum Command {
+    /// Translate joystick input with a config
+    Run(RunOpt),
+
+    /// List connected HID devices
+    ListDevices,
+
+    /// Show joystick input live, and what a config would send for it without sending any
+    Monitor {
+        #[structopt(flatten)]
+        device: DeviceOpt,
+        /// Config file to show deadzones and actions for
+        #[structopt(short, long, value_name = "PATH", conflicts_with = "preset")]
+        config: Option<String>,
+        /// Built-in config to show deadzones and actions for
+        #[structopt(short, long, value_name = "NAME")]
+        preset: Option<String>,
+        #[structopt(flatten)]
+        overrides: Overrides,
+    },
+
+    /// Measure the stick's resting noise and range, and suggest deadzones
+    Calibrate {
+        #[structopt(flatten)]
+        device: DeviceOpt,
+        /// Seconds to sample each step for
+        #[structopt(long, value_name = "SECONDS", default_value = "3")]
+        seconds: u64,
+    },
+
+    /// Check a config without opening the device
+    Validate {
+        #[structopt(flatten)]
+        source: SourceOpt,
+        #[structopt(flatten)]
+        overrides: Overrides,
+    },
+
+    /// Translate a config between formats, chosen by file extension
+    Convert {
+        #[structopt(name = "INPUT")]
+        input: String,
+        #[structopt(name = "OUTPUT")]
+        output: String,
+    },
+
+    /// Rewrite a config in place with the newest schema
+    Migrate {
+        #[structopt(name = "CONFIG")]
+        config: String,
+    },
+
+    /// Print a config with everything it extends merged in
+    Resolve {
+        #[structopt(name = "CONFIG")]
+        config: String,
+    },
+
+    /// Write the JSON Schema of config files, or print it without OUTPUT
+    Schema {
+        #[structopt(name = "OUTPUT")]
+        output: Option<String>,
+    },
+
+    /// List the built-in presets, or write one to OUTPUT to customize
+    Preset {
+        #[structopt(name = "NAME", requires = "OUTPUT")]
+        name: Option<String>,
+        #[structopt(name = "OUTPUT")]
+        output: Option<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RunOpt {
+    #[structopt(flatten)]
+    pub source: SourceOpt,
+
+    #[structopt(flatten)]
+    pub device: DeviceOpt,
+
+    /// Print the input the config would send, with timestamps, instead of sending it
+    #[structopt(long)]
+    pub dry_run: bool,
+
+    /// Print every event from the joystick, e.g. button_pressed(trigger)
+    #[structopt(short, long)]
+    pub verbose: bool,
+
+    #[structopt(flatten)]
+    pub overrides: Overrides,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SourceOpt {
+    /// Config file: JSON, TOML, YAML or RON
+    #[structopt(short, long, value_name = "PATH", required_unless = "preset", conflicts_with = "preset")]
+    pub config: Option<String>,
+
+    /// Built-in config, see `rattacker preset`
+    #[structopt(short, long, value_name = "NAME")]
+    pub preset: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DeviceOpt {
+    /// Joystick to open as VID:PID in hex, see `rattacker list-devices`
+    #[structopt(short, long, value_name = "VID:PID", default_value = "046d:c214")]
+    pub device: DeviceId,
+}
+
+// Config values set from the command line
+// Applied to every profile and layer, and checked like values from the file
+// NOTE: not a doc comment, structopt would use it as the subcommand's description
+#[derive(Debug, StructOpt)]
+pub struct Overrides {
+    /// Send keys as Normal or DirectX
+    #[structopt(long, value_name = "MODE", possible_values = &["Normal", "DirectX"], parse(try_from_str = parse_mode))]
+    pub mode: Option<Mode>,
+
+    /// Deadzone of both stick axes
+    #[structopt(long, value_name = "DEADZONE")]
+    pub deadzone: Option<f32>,
+
+    /// Deadzone of the stick's x-axis, takes precedence over --deadzone
+    #[structopt(long, value_name = "DEADZONE")]
+    pub x_deadzone: Option<f32>,
+
+    /// Deadzone of the stick's y-axis, takes precedence over --deadzone
+    #[structopt(long, value_name = "DEADZONE")]
+    pub y_deadzone: Option<f32>,
+
+    /// Set any value in the file, e.g. joystick.Mouse.x_axis.dpi=800 (VALUE is JSON, or else a string)
+    #[structopt(long = "set", value_name = "PATH=VALUE", number_of_values = 1, parse(try_from_str = parse_assignment))]
+    pub set: Vec<(String, Value)>,
+}
+
+impl Command {
+    /// Parses the process arguments, exiting with help text or a usage error
+    pub fn parse() -> Command {
+        let args = shorthand(env::args_os().collect());
+
+        match Command::from_iter_safe(args) {
+            Ok(command) => command,
+            Err(e) if e.kind == ErrorKind::HelpDisplayed || e.kind == ErrorKind::VersionDisplayed => e.exit(),
+            Err(e) => {
+                eprintln!("{}", e.message);
+                process::exit(2);
+            }
+        }
+    }
+}
+
+/// Keeps older command lines working
+///   `rattacker CONFIG` => `rattacker run --config CONFIG`, options may come before CONFIG
+///   `rattacker --preset NAME` => `rattacker run --preset NAME`
+///   `rattacker validate CONFIG` => `rattacker validate --config CONFIG`
+fn shorthand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let first = match args.get(1).and_then(|a| a.to_str()) {
+        Some(first) => first.to_string(),
+        None => return args,
+    };
+
+    let is_help = ["-h", "--help", "-V", "--version"].contains(&first.as_str());
+    if !is_help && !SUBCOMMANDS.contains(&first.as_str()) {
+        args.insert(1, "run".into());
+    }
+
+    let command = args[1].to_str().unwrap_or("").to_string();
+    if command == "run" || command == "validate" {
+        if let Some(i) = bare_path(&args[2..]) {
+            args.insert(2 + i, "--config".into());
+        }
+    }
+
+    args
+}
+
+/// Index of the first argument that is neither an option nor an option's value
+fn bare_path(args: &[OsString]) -> Option<usize> {
+    let mut value_next = false;
+    for (i, arg) in args.iter().enumerate() {
+        // Not UTF-8, so not one of our options
+        let arg = arg.to_str().unwrap_or("");
+        if value_next {
+            value_next = false;
+        } else if arg.starts_with('-') {
+            value_next = VALUE_OPTIONS.contains(&arg);
+        } else {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+fn parse_mode(s: &str) -> Result<Mode, String> {
+    match s {
+        "Normal" => Ok(Mode::Normal),
+        "DirectX" => Ok(Mode::DirectX),
+        _ => Err(format!("Unknown mode '{}'", s)),
+    }
+}
+
+fn parse_assignment(s: &str) -> Result<(String, Value), String> {
+    let mut parts = s.splitn(2, '=');
+    let path = parts.next().unwrap_or("");
+    let value = parts.next()
+        .ok_or_else(|| format!("Expected PATH=VALUE, not '{}'", s))?;
+    let value = serde_json::from_str(value)
+        .unwrap_or_else(|_| Value::String(value.to_string()));
+
+    Ok((path.to_string(), value))
+}
+
+impl Overrides {
+    /// `--set` applies to the file as read, before it becomes a ProfileSet
+    pub fn apply_value(&self, value: &mut Value) -> Result<(), String> {
+        for (path, new) in self.set.iter() {
+            set(value, path, new.clone())
+                .map_err(|e| format!("Cannot --set {}: {}", path, e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn apply(&self, profiles: &mut ProfileSet) {
+        let x_deadzone = self.x_deadzone.or(self.deadzone);
+        let y_deadzone = self.y_deadzone.or(self.deadzone);
+
+        for profile in profiles.profiles.iter_mut() {
+            let cfg = &mut profile.config;
+            if let Some(mode) = self.mode {
+                cfg.mode = mode;
+            }
+
+            let layers = cfg.layers.iter_mut().filter_map(|l| l.joystick.as_mut());
+            for joystick in cfg.joystick.iter_mut().chain(layers) {
+                let (x, y) = match joystick {
+                    JoystickConfig::Keys { x_axis, y_axis } => (&mut x_axis.deadzone, &mut y_axis.deadzone),
+                    JoystickConfig::Mouse { x_axis, y_axis } => (&mut x_axis.deadzone, &mut y_axis.deadzone),
+                    JoystickConfig::Scroll { x_axis, y_axis } => (&mut x_axis.deadzone, &mut y_axis.deadzone),
+                    // One deadzone around the center, either override sets it
+                    JoystickConfig::Directions(directions) => {
+                        if let Some(deadzone) = x_deadzone.or(y_deadzone) {
+                            directions.deadzone = deadzone;
+                        }
+                        continue;
+                    }
+                };
+                if let Some(deadzone) = x_deadzone {
+                    *x = deadzone;
+                }
+                if let Some(deadzone) = y_deadzone {
+                    *y = deadzone;
+                }
+            }
+        }
+    }
+}
+
+/// Sets the value at a path like the ones validation reports, e.g. `layers[0].joystick`
+/// Missing object keys are created
+fn set(value: &mut Value, path: &str, new: Value) -> Result<(), String> {
+    let mut target = value;
+    for segment in path.split('.') {
+        let (key, indices) = match segment.find('[') {
+            Some(i) => segment.split_at(i),
+            None => (segment, ""),
+        };
+
+        if !key.is_empty() {
+            if target.is_null() {
+                *target = Value::Object(Default::default());
+            }
+            target = match target {
+                Value::Object(obj) => obj.entry(key).or_insert(Value::Null),
+                _ => return Err(format!("'{}' is not in an object", key)),
+            };
+        }
+
+        for index in indices.split('[').skip(1) {
+            let index: usize = index.strip_suffix(']')
+                .and_then(|i| i.parse().ok())
+                .ok_or_else(|| format!("Bad index in '{}'", segment))?;
+            target = target.as_array_mut()
+                .and_then(|array| array.get_mut(index))
+                .ok_or_else(|| format!("There is no {}[{}]", key, index))?;
+        }
+    }
+
+    *target = new;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(line: &str) -> String {
+        let args = line.split(' ').map(OsString::from).collect();
+        shorthand(args).iter()
+            .map(|a| a.to_str().unwrap())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn bare_path_runs() {
+        assert_eq!(expand("rattacker cfg.json"), "rattacker run --config cfg.json");
+        assert_eq!(expand("rattacker validate cfg.json"), "rattacker validate --config cfg.json");
+        assert_eq!(expand("rattacker run cfg.json"), "rattacker run --config cfg.json");
+    }
+
+    #[test]
+    fn bare_path_after_options() {
+        assert_eq!(expand("rattacker --dry-run cfg.json"), "rattacker run --dry-run --config cfg.json");
+        assert_eq!(expand("rattacker -v cfg.json"), "rattacker run -v --config cfg.json");
+        assert_eq!(expand("rattacker --deadzone 0.2 cfg.json --dry-run"),
+            "rattacker run --deadzone 0.2 --config cfg.json --dry-run");
+        assert_eq!(expand("rattacker validate --set mode=DirectX cfg.json"),
+            "rattacker validate --set mode=DirectX --config cfg.json");
+    }
+
+    #[test]
+    fn option_values_are_not_paths() {
+        assert_eq!(expand("rattacker --preset wasd"), "rattacker run --preset wasd");
+        assert_eq!(expand("rattacker -d 046d:c214 --config cfg.json"), "rattacker run -d 046d:c214 --config cfg.json");
+        assert_eq!(expand("rattacker --mode=DirectX cfg.json"), "rattacker run --mode=DirectX --config cfg.json");
+    }
+
+    #[test]
+    fn other_commands_unchanged() {
+        for line in ["rattacker", "rattacker --help", "rattacker monitor -c cfg.json", "rattacker migrate cfg.json"].iter() {
+            assert_eq!(expand(line), *line);
+        }
+    }
+
+    #[test]
+    fn expanded_line_parses() {
+        let args = shorthand(["rattacker", "-v", "cfg.json"].iter().map(OsString::from).collect());
+        match Command::from_iter_safe(args).unwrap() {
+            Command::Run(opt) => {
+                assert!(opt.verbose);
+                assert_eq!(opt.source.config.as_deref(), Some("cfg.json"));
+            }
+            command => panic!("expected run, not {:?}", command),
+        }
+    }
+}
@@ -1,32 +1,523 @@
-use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, EnumAccess, IntoDeserializer, VariantAccess, Visitor};
+use schemars::JsonSchema;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Metadata, NumberValidation, Schema, SchemaObject, StringValidation, SubschemaValidation};
 
 pub const BUTTON_LEN: usize = 11;
 
+/// Schema version written to new config files
+/// Older documents are upgraded by migrate.rs before being read
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Physical names of the buttons, in button number order
+/// Base buttons go clockwise from the front of the left side
+pub const BUTTON_NAMES: [&str; BUTTON_LEN] = [
+    "trigger",
+    "thumb_down",
+    "thumb_up",
+    "thumb_left",
+    "thumb_right",
+    "base_left_front",
+    "base_left_back",
+    "base_back_left",
+    "base_back_right",
+    "base_right_back",
+    "base_right_front",
+];
+
 // TODO: z-axis
 // NOTE: polling delay should NOT be configurable
 // hidapi accumulates packets and returns  1 packet per read()
 //   Delaying a read() causes packets to accumulate, leading to laggy input
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
+    /// Schema version, older versions are upgraded when read
+    /// Files from before there were versions have none and are version 1
+    #[serde(default = "first_version")]
+    #[schemars(range(min = 1, max = "CONFIG_VERSION"))]
+    pub version: u32,
     pub mode: Mode,
-    pub buttons: [Option<Key>; BUTTON_LEN],
+    #[serde(with = "buttons")]
+    #[schemars(schema_with = "buttons::schema")]
+    pub buttons: [Option<Binding>; BUTTON_LEN],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub joystick: Option<JoystickConfig>,
+    /// Alternate bindings switched on by buttons
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<Layer>,
+    /// Not switched by layers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throttle: Option<ThrottleConfig>,
+    /// How keys held by several bindings at once behave, Shared unless listed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub key_policies: BTreeMap<Key, KeyPolicy>,
+    /// Stick movements that fire an action, alongside the stick's own bindings
+    /// Not switched by layers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gestures: Vec<GestureConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+/// Several named configs in one file
+/// A file holding a single Config is read as a set with one "default" profile
+pub struct ProfileSet {
+    /// Schema version, older versions are upgraded when read
+    /// Files from before there were versions have none and are version 1
+    #[serde(default = "first_version")]
+    #[schemars(range(min = 1, max = "CONFIG_VERSION"))]
+    pub version: u32,
+    pub profiles: Vec<Profile>,
+    /// Button combinations that switch between profiles at runtime
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub switches: Vec<ProfileSwitch>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Profile {
+    pub name: String,
+    pub config: Config,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ProfileSwitch {
+    /// Buttons that must all be held
+    pub buttons: Vec<Button>,
+    pub target: SwitchTarget,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum SwitchTarget {
+    /// Cycle to the next profile in the file
+    Next,
+    /// Jump to the profile with this name
+    Profile(String),
+}
+
+impl ProfileSet {
+    pub fn from_value(value: serde_json::Value) -> Result<ProfileSet, String> {
+        let set = if value.get("profiles").is_some() {
+            serde_json::from_value(value).map_err(|e| e.to_string())?
+        } else {
+            let config = serde_json::from_value(value).map_err(|e| e.to_string())?;
+            ProfileSet {
+                version: CONFIG_VERSION,
+                profiles: vec![Profile {
+                    name: "default".to_string(),
+                    config,
+                }],
+                switches: Vec::new(),
+            }
+        };
+
+        if set.profiles.is_empty() {
+            return Err("Profile set has no profiles".to_string());
+        }
+
+        Ok(set)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+/// Alternate bindings switched on by a button
+/// Later layers in Config::layers take precedence when several are active
+pub struct Layer {
+    pub name: String,
+    /// Button that switches this layer
+    /// The button's own binding is ignored
+    pub button: Button,
+    pub activation: LayerActivation,
+    /// Unbound buttons fall through to the layer below
+    #[serde(with = "buttons")]
+    #[schemars(schema_with = "buttons::schema")]
+    pub buttons: [Option<Binding>; BUTTON_LEN],
+    /// `null` keeps the joystick config of the layer below
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub joystick: Option<JoystickConfig>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+/// What a key does when a binding presses it while another binding already holds it
+/// Either way the key is only released once every binding let go of it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum KeyPolicy {
+    /// Stays down, the later press sends nothing, used for keys without a policy
+    Shared,
+    /// Sends the key up and down again, so the later press registers as a new one
+    Retrigger,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum LayerActivation {
+    /// Active while the button is held
+    Hold,
+    /// Each press switches the layer on or off
+    Toggle,
+}
+
+/// A physical button, by index into State::buttons
+/// Written as its name (e.g. `"trigger"`), read from a name or a button number (1-11)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Button(pub usize);
+
+impl Button {
+    pub fn name(&self) -> &'static str {
+        BUTTON_NAMES[self.0]
+    }
+
+    /// Accepts a physical name or a button number (1-11)
+    pub fn parse(s: &str) -> Option<Button> {
+        if let Some(i) = BUTTON_NAMES.iter().position(|name| *name == s) {
+            return Some(Button(i));
+        }
+
+        match s.parse::<usize>() {
+            Ok(n) if (1..=BUTTON_LEN).contains(&n) => Some(Button(n - 1)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name(), self.0 + 1)
+    }
+}
+
+impl Serialize for Button {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl JsonSchema for Button {
+    fn schema_name() -> String {
+        "Button".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let names = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(BUTTON_NAMES.iter().map(|name| (*name).into()).collect()),
+            ..Default::default()
+        };
+
+        let number_strings = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(BUTTON_NUMBER_PATTERN.to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let numbers = SchemaObject {
+            instance_type: Some(InstanceType::Integer.into()),
+            number: Some(Box::new(NumberValidation {
+                minimum: Some(1.0),
+                maximum: Some(BUTTON_LEN as f64),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some("A physical button name or a button number (1-11)".to_string()),
+                ..Default::default()
+            })),
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![names.into(), number_strings.into(), numbers.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }.into()
+    }
+}
+
+/// Button numbers 1-11 written as strings, e.g. map keys
+const BUTTON_NUMBER_PATTERN: &str = "^([1-9]|1[01])$";
+
+impl<'de> Deserialize<'de> for Button {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ButtonVisitor)
+    }
+}
+
+struct ButtonVisitor;
+
+impl<'de> Visitor<'de> for ButtonVisitor {
+    type Value = Button;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a button number (1-{}) or one of {}", BUTTON_LEN, BUTTON_NAMES.join(", "))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Button, E> {
+        Button::parse(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Button, E> {
+        if (1..=BUTTON_LEN as u64).contains(&v) {
+            Ok(Button(v as usize - 1))
+        } else {
+            Err(E::invalid_value(de::Unexpected::Unsigned(v), &self))
+        }
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Button, E> {
+        if (1..=BUTTON_LEN as i64).contains(&v) {
+            Ok(Button(v as usize - 1))
+        } else {
+            Err(E::invalid_value(de::Unexpected::Signed(v), &self))
+        }
+    }
+}
+
+/// Serde for a full set of button bindings
+/// Reads either a map keyed by Button or the old positional array of BUTTON_LEN
+/// Always writes a map keyed by name
+/// Unbound buttons are left out of the map, or `null` in the array
+mod buttons {
+    use std::fmt;
+
+    use serde::{Serializer, Deserializer};
+    use serde::de::{self, MapAccess, SeqAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use schemars::gen::SchemaGenerator;
+    use schemars::schema::{InstanceType, ObjectValidation, Schema, SchemaObject};
+
+    use super::{Binding, Button, BUTTON_LEN, BUTTON_NAMES, BUTTON_NUMBER_PATTERN};
+
+    /// Only the map is described, the positional array is migrated away
+    pub fn schema(gen: &mut SchemaGenerator) -> Schema {
+        let binding = gen.subschema_for::<Binding>();
+        let mut object = ObjectValidation::default();
+        for name in BUTTON_NAMES.iter() {
+            object.properties.insert(name.to_string(), binding.clone());
+        }
+        object.pattern_properties.insert(BUTTON_NUMBER_PATTERN.to_string(), binding);
+        object.additional_properties = Some(Box::new(Schema::Bool(false)));
+
+        SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            object: Some(Box::new(object)),
+            ..Default::default()
+        }.into()
+    }
+
+    pub fn serialize<S: Serializer>(
+        buttons: &[Option<Binding>; BUTTON_LEN],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (i, b) in buttons.iter().enumerate() {
+            if let Some(b) = b {
+                map.serialize_entry(&Button(i), b)?;
+            }
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[Option<Binding>; BUTTON_LEN], D::Error> {
+        deserializer.deserialize_any(ButtonsVisitor)
+    }
+
+    struct ButtonsVisitor;
+
+    impl<'de> Visitor<'de> for ButtonsVisitor {
+        type Value = [Option<Binding>; BUTTON_LEN];
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a map of buttons to bindings or an array of {} bindings", BUTTON_LEN)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut buttons = [None; BUTTON_LEN];
+            let mut seen = [false; BUTTON_LEN];
+            while let Some(button) = map.next_key::<Button>()? {
+                if seen[button.0] {
+                    return Err(de::Error::custom(
+                        format!("button {} is bound more than once", button)
+                    ));
+                }
+                seen[button.0] = true;
+                buttons[button.0] = Some(map.next_value::<Binding>()?);
+            }
+
+            Ok(buttons)
+        }
+
+        // Backwards compatibility with the positional array
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut buttons = [None; BUTTON_LEN];
+            for (i, button) in buttons.iter_mut().enumerate() {
+                match seq.next_element::<Option<Binding>>()? {
+                    Some(b) => *button = b,
+                    None => return Err(de::Error::invalid_length(i, &self)),
+                }
+            }
+
+            if seq.next_element::<Option<Binding>>()?.is_some() {
+                return Err(de::Error::invalid_length(BUTTON_LEN + 1, &self));
+            }
+
+            Ok(buttons)
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(untagged)]
+/// What a button does when pressed
+/// A bare key (e.g. `"LMB"`) is pressed and released along with the button
+pub enum Binding {
+    Key(Key),
+    Action(ButtonAction),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum ButtonAction {
+    /// Repeatedly press and release a key while the button is held
+    Turbo(TurboConfig),
+
+    /// First press holds the key down, the next press releases it
+    Toggle(Key),
+
+    /// Turn the mouse wheel on every press, and optionally while held
+    Scroll(ScrollConfig),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TurboConfig {
+    pub key: Key,
+    /// Presses per second
+    #[schemars(schema_with = "positive_schema")]
+    pub rate: f32,
+    /// Fraction of each press period the key is held down, in range [0.0, 1.0]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub duty_cycle: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ScrollConfig {
+    pub wheel: Wheel,
+    /// Notches per scroll, negative scrolls down or left
+    /// Fractions of a notch need programs with high-resolution scrolling
+    pub notches: f32,
+    /// Scrolls per second while the button is held, after the first
+    /// Without it the button scrolls once per press
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<f32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Wheel {
+    /// Positive scrolls up
+    Vertical,
+    /// Positive scrolls right
+    Horizontal,
+}
+
+// NOTE: not #[serde(untagged)]
+//   Untagged enums go through deserialize_any, which loses variant names in RON
+//   Instead, read a Binding as one enum whose variants are every Key plus every ButtonAction
+impl<'de> Deserialize<'de> for Binding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_enum("Binding", &["Turbo", "Toggle", "Scroll"], BindingVisitor)
+    }
+}
+
+struct BindingVisitor;
+
+impl<'de> Visitor<'de> for BindingVisitor {
+    type Value = Binding;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a key or a button action")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Binding, A::Error> {
+        let (VariantName(name), variant) = data.variant()?;
+        match name.as_str() {
+            "Turbo" => variant.newtype_variant()
+                .map(|turbo| Binding::Action(ButtonAction::Turbo(turbo))),
+
+            "Toggle" => variant.newtype_variant()
+                .map(|k| Binding::Action(ButtonAction::Toggle(k))),
+
+            "Scroll" => variant.newtype_variant()
+                .map(|scroll| Binding::Action(ButtonAction::Scroll(scroll))),
+
+            _ => {
+                variant.unit_variant()?;
+                let k: Result<Key, de::value::Error> =
+                    Key::deserialize(name.as_str().into_deserializer());
+                k.map(Binding::Key).map_err(de::Error::custom)
+            }
+        }
+    }
+}
+
+/// Enum variant names are identifiers, not strings, in some formats (e.g. RON)
+struct VariantName(String);
+
+impl<'de> Deserialize<'de> for VariantName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct VariantNameVisitor;
+
+        impl<'de> Visitor<'de> for VariantNameVisitor {
+            type Value = VariantName;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a variant name")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<VariantName, E> {
+                Ok(VariantName(v.to_string()))
+            }
+        }
+
+        deserializer.deserialize_identifier(VariantNameVisitor)
+    }
+}
+
+impl Binding {
+    /// The key this binding sends, if any
+    pub fn key(&self) -> Option<Key> {
+        match self {
+            Binding::Key(k) => Some(*k),
+            Binding::Action(ButtonAction::Turbo(turbo)) => Some(turbo.key),
+            Binding::Action(ButtonAction::Toggle(k)) => Some(*k),
+            Binding::Action(ButtonAction::Scroll(_)) => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum Mode {
+    /// Send virtual key codes
     Normal,
+    /// Send scan codes, for games that ignore virtual key codes
     DirectX,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum Axis {
     X,
     Y,
     Z,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum JoystickConfig {
     Keys {
         x_axis: AxisKeyConfig,
@@ -37,19 +528,172 @@ pub enum JoystickConfig {
         x_axis: AxisMouseConfig,
         y_axis: AxisMouseConfig,
     },
+
+    /// The x-axis turns the horizontal wheel, the y-axis the vertical one
+    Scroll {
+        x_axis: AxisScrollConfig,
+        y_axis: AxisScrollConfig,
+    },
+
+    /// Keys held by the direction the stick points in rather than by each axis, like a D-pad
+    Directions(DirectionsConfig),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum ThrottleConfig {
+    /// Scroll while the throttle is away from its center
+    Scroll {
+        wheel: Wheel,
+        z_axis: AxisScrollConfig,
+    },
+}
+
+impl JoystickConfig {
+    /// (x-axis, y-axis)
+    pub fn deadzones(&self) -> (f32, f32) {
+        match self {
+            JoystickConfig::Keys { x_axis, y_axis } => (x_axis.deadzone, y_axis.deadzone),
+            JoystickConfig::Mouse { x_axis, y_axis } => (x_axis.deadzone, y_axis.deadzone),
+            JoystickConfig::Scroll { x_axis, y_axis } => (x_axis.deadzone, y_axis.deadzone),
+            JoystickConfig::Directions(directions) => (directions.deadzone, directions.deadzone),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+/// Each bound direction covers an angular sector centered on it
+/// The keys of every sector the stick points into are held, together
+pub struct DirectionsConfig {
+    /// Directions without keys hold nothing
+    pub keys: BTreeMap<Direction, Vec<Key>>,
+    /// Degrees each sector covers
+    /// Defaults to 90 if only Up, Right, Down and Left are bound, 45 otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0.0, max = 360.0))]
+    pub width: Option<f32>,
+    /// Degrees each sector reaches past its edges into its neighbours,
+    ///   where both are held
+    #[serde(default)]
+    #[schemars(range(min = 0.0))]
+    pub overlap: f32,
+    /// Radius around the center that holds nothing, in range [0.0, 1.0)
+    #[schemars(schema_with = "deadzone_schema")]
+    pub deadzone: f32,
+}
+
+impl DirectionsConfig {
+    /// Degrees each sector covers, with the default applied
+    pub fn sector_width(&self) -> f32 {
+        self.width.unwrap_or_else(|| {
+            if self.keys.keys().all(|d| d.is_cardinal()) { 90.0 } else { 45.0 }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+/// An action fired by a stick gesture
+/// The action is pressed once the gesture is complete and released `hold` seconds later
+pub struct GestureConfig {
+    pub gesture: Gesture,
+    pub action: Binding,
+    /// Seconds the whole gesture may take
+    #[serde(default = "gesture_window")]
+    #[schemars(schema_with = "positive_schema")]
+    pub window: f32,
+    /// Deflection the stick has to reach, in range (0.0, 1.0]
+    #[serde(default = "gesture_threshold")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub threshold: f32,
+    /// Radius around the center a flick has to return to, in range [0.0, threshold)
+    #[serde(default = "gesture_center")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub center: f32,
+    /// Seconds the action is held for
+    #[serde(default = "gesture_hold")]
+    #[schemars(range(min = 0.0))]
+    pub hold: f32,
+}
+
+fn gesture_window() -> f32 {
+    0.3
+}
+
+fn gesture_threshold() -> f32 {
+    0.8
+}
+
+fn gesture_center() -> f32 {
+    0.2
+}
+
+fn gesture_hold() -> f32 {
+    0.05
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum Gesture {
+    /// Out past the threshold in a direction and back to the center
+    Flick(Direction),
+    /// A quarter turn past the threshold, e.g. from Down to Right counter-clockwise
+    QuarterCircle { from: Direction, rotation: Rotation },
+    /// A full turn past the threshold, starting anywhere
+    Circle(Rotation),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Rotation {
+    /// Up, Right, Down, Left
+    Clockwise,
+    /// Up, Left, Down, Right
+    CounterClockwise,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+pub enum Direction {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+impl Direction {
+    /// Degrees clockwise from Up
+    pub fn angle(self) -> f32 {
+        match self {
+            Direction::Up => 0.0,
+            Direction::UpRight => 45.0,
+            Direction::Right => 90.0,
+            Direction::DownRight => 135.0,
+            Direction::Down => 180.0,
+            Direction::DownLeft => 225.0,
+            Direction::Left => 270.0,
+            Direction::UpLeft => 315.0,
+        }
+    }
+
+    pub fn is_cardinal(self) -> bool {
+        matches!(self, Direction::Up | Direction::Right | Direction::Down | Direction::Left)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 /// Implements mouse-style input
 /// Maps the normalized axis value ([-1.0, 1.0]) directly to inches moved
 pub struct AxisMouseConfig {
     /// Defines the dots-per-pixel function
     pub dots_per_pixel: MouseMode,
+    /// Dots moved per inch of full deflection
     pub dpi: f32,
+    /// Fraction of the axis ignored around the center, in range [0.0, 1.0)
+    #[schemars(schema_with = "deadzone_schema")]
     pub deadzone: f32,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum MouseMode {
 
     /// g(f) = c
@@ -58,6 +702,7 @@ pub enum MouseMode {
     /// g(f) = (1 - |f|) * m + (bias * sign(m))
     Linear {
         m: f32,
+        #[schemars(schema_with = "positive_schema")]
         bias: f32,
     },
 
@@ -65,8 +710,10 @@ pub enum MouseMode {
     /// g(f) = minimum(|clamp((1 - coef(f)), 0, 1) * target|, |min|) * sign(target)
     Logistic {
         target: f32,
+        #[schemars(range(min = 0.0))]
         min: f32,
         a: f32,
+        #[schemars(schema_with = "positive_schema")]
         b: f32,
         c: f32,
         d: f32,
@@ -74,15 +721,99 @@ pub enum MouseMode {
     },
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct AxisKeyConfig {
     pub positive: Key,
     pub negative: Key,
+    /// Fraction of the axis ignored around the center, in range [0.0, 1.0)
+    #[schemars(schema_with = "deadzone_schema")]
     pub deadzone: f32,
+    /// Pulse the key instead of holding it, for finer control than all or nothing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm: Option<PwmConfig>,
+    /// Other keys to hold past further thresholds, in increasing order
+    /// Only the keys that differ are pressed or released between stages
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<AxisStage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+/// Keys held past a threshold, in place of those of the stage below
+/// e.g. `{ "threshold": 0.85, "positive": ["W", "Shift"] }` adds Shift to W
+pub struct AxisStage {
+    /// Deflection past which this stage applies, in range (deadzone, 1.0)
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub threshold: f32,
+    /// None keeps the keys of the stage below
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub positive: Option<Vec<Key>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negative: Option<Vec<Key>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+/// The key is held for part of every period, past the deadzone
+/// duty cycle = (|f| - deadzone) / (1 - deadzone), so full deflection holds it
+pub struct PwmConfig {
+    /// Seconds per press and release
+    #[schemars(schema_with = "positive_schema")]
+    pub period: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+/// Scrolls faster the further the axis is deflected
+/// notches per second = rate * ((|f| - deadzone) / (1 - deadzone))^exponent
+pub struct AxisScrollConfig {
+    /// Notches per second at full deflection
+    #[schemars(schema_with = "positive_schema")]
+    pub rate: f32,
+    /// 1.0 is linear, higher is finer near the deadzone
+    #[serde(default = "linear_exponent")]
+    #[schemars(schema_with = "positive_schema")]
+    pub exponent: f32,
+    /// Scroll down (or left) for positive deflection
+    #[serde(default)]
+    pub invert: bool,
+    /// Only scroll whole notches, for programs without high-resolution scrolling
+    #[serde(default)]
+    pub whole_notches: bool,
+    /// Fraction of the axis ignored around the center, in range [0.0, 1.0)
+    #[schemars(schema_with = "deadzone_schema")]
+    pub deadzone: f32,
+}
+
+fn linear_exponent() -> f32 {
+    1.0
+}
+
+// NOTE: #[schemars(range(..))] is inclusive, these bounds are not
+
+fn deadzone_schema(_: &mut SchemaGenerator) -> Schema {
+    number_schema(NumberValidation {
+        minimum: Some(0.0),
+        exclusive_maximum: Some(1.0),
+        ..Default::default()
+    })
+}
+
+fn positive_schema(_: &mut SchemaGenerator) -> Schema {
+    number_schema(NumberValidation {
+        exclusive_minimum: Some(0.0),
+        ..Default::default()
+    })
+}
+
+fn number_schema(bounds: NumberValidation) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::Number.into()),
+        format: Some("float".to_string()),
+        number: Some(Box::new(bounds)),
+        ..Default::default()
+    }.into()
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
 pub enum Key {
     A,
     B,
@@ -0,0 +1,132 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use hidapi::{HidApi, HidDevice};
+
+use super::raw_input::Packet;
+use super::dispatch::State;
+
+pub const PACKET_LENGTH: usize = 5;
+
+/// The Logitech Attack3
+pub const ATTACK3: DeviceId = DeviceId {
+    vid: 0x046d,
+    pid: 0xc214,
+};
+
+/// USB vendor and product id, written `VID:PID` in hex (e.g. `046d:c214`)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DeviceId {
+    pub vid: u16,
+    pub pid: u16,
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vid, self.pid)
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DeviceId, String> {
+        let error = || format!("Expected a device as VID:PID in hex (e.g. {}), not '{}'", ATTACK3, s);
+        let mut parts = s.splitn(2, ':');
+        let vid = parts.next().ok_or_else(error)?;
+        let pid = parts.next().ok_or_else(error)?;
+
+        Ok(DeviceId {
+            vid: u16::from_str_radix(vid, 16).map_err(|_| error())?,
+            pid: u16::from_str_radix(pid, 16).map_err(|_| error())?,
+        })
+    }
+}
+
+/// One input report from the joystick
+pub struct Report {
+    pub bytes: [u8; PACKET_LENGTH],
+    pub packet: Packet,
+    pub state: State,
+}
+
+/// An open joystick, read without blocking
+/// The stick is zeroed on the first packet, which is not reported
+pub struct Joystick {
+    device: HidDevice,
+    buffer: [u8; 1024],
+    zero: Option<[u8; 2]>,
+}
+
+impl Joystick {
+    pub fn open(hidapi: &HidApi, id: DeviceId) -> Result<Joystick, Box<dyn Error>> {
+        let device = hidapi.open(id.vid, id.pid)
+            .map_err(|e| format!("Could not open {} ({}), see `rattacker list-devices`", id, e))?;
+        device.set_blocking_mode(false)?;
+
+        Ok(Joystick {
+            device,
+            buffer: [0u8; 1024],
+            zero: None,
+        })
+    }
+
+    pub fn is_zeroed(&self) -> bool {
+        self.zero.is_some()
+    }
+
+    /// Every report that arrived since the last read
+    /// Empty if nothing arrived
+    pub fn read(&mut self) -> Result<Vec<Report>, Box<dyn Error>> {
+        let read_len = self.device.read(&mut self.buffer)?;
+
+        let packet_count = read_len / PACKET_LENGTH;
+        let mut read = Vec::with_capacity(packet_count);
+        for i in 0..packet_count {
+            let start = i * PACKET_LENGTH;
+            let mut bytes = [0u8; PACKET_LENGTH];
+            bytes.copy_from_slice(&self.buffer[start..start + PACKET_LENGTH]);
+            let p = Packet::parse(&bytes)?;
+
+            let zero = match self.zero {
+                Some(zero) => zero,
+                None => {
+                    self.zero = Some([p.x_axis, p.y_axis]);
+                    continue;
+                }
+            };
+
+            let state = State::from_packet(zero, p.clone());
+            read.push(Report {
+                bytes,
+                packet: p,
+                state,
+            });
+        }
+
+        Ok(read)
+    }
+}
+
+/// Prints every HID device, marking the ones rattacker knows
+pub fn list_devices(hidapi: &HidApi) {
+    let mut count = 0;
+    for info in hidapi.device_list() {
+        let id = DeviceId {
+            vid: info.vendor_id(),
+            pid: info.product_id(),
+        };
+
+        println!("{}  {} {}{}",
+            id,
+            info.manufacturer_string().unwrap_or("(unknown)"),
+            info.product_string().unwrap_or("(unknown)"),
+            if id == ATTACK3 { "  <- Attack3" } else { "" });
+        count += 1;
+    }
+
+    if count == 0 {
+        println!("No HID devices found");
+    }
+}
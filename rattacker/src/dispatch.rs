@@ -1,13 +1,14 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
 
 use super::raw_input::Packet;
 use super::config::*;
-
-macro_rules! printHandler {
-    ($msg: expr) => (Box::new(|| { println!("{}", $msg); }));
-    (joy => $msg: expr) => (Box::new(|_| { println!("{}", $msg); }))
-}
+use super::actions::{Directions, Pwm, Scroll, Stages, Turbo, Toggle};
+use super::events::{Event, Events, Zone};
+use super::gestures::Recognizer;
 
 macro_rules! handler {
     (UP => $d: expr, $k: expr) => {{
@@ -39,21 +40,203 @@ macro_rules! handler {
     }};
 }
 
+/// Wheel movement of one notch, scroll deltas are in fractions of it
+pub const WHEEL_DELTA: i32 = 120;
+
+/// Most seconds of scrolling one step of an axis can send
+const MAX_SCROLL_STEP: f32 = 0.1;
+
+/// One input for a Dispatcher to send
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    KeyDown(Key),
+    KeyUp(Key),
+    RelMouse { dx: i32, dy: i32 },
+    Scroll { wheel: Wheel, delta: i32 },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::KeyDown(k) => write!(f, "key_down({:?})", k),
+            Action::KeyUp(k) => write!(f, "key_up({:?})", k),
+            Action::RelMouse { dx, dy } => write!(f, "rel_mouse({:+}, {:+})", dx, dy),
+            Action::Scroll { wheel, delta } => write!(f, "scroll({:?}, {:+})", wheel, delta),
+        }
+    }
+}
+
+/// Where a Manager sends its output, e.g. the OS (win_input) or a log (record)
 pub trait Dispatcher {
     fn from_cfg(cfg: &Config) -> Self;
     fn key_up(&self, k: Key);
     fn key_down(&self, k: Key);
-    fn rel_mouse_x(&self, r: i32);
-    fn rel_mouse_y(&self, r: i32);
+    /// Moves the mouse by dx and dy pixels in one motion
+    fn rel_mouse(&self, dx: i32, dy: i32);
+    /// Turns a wheel by delta / WHEEL_DELTA notches
+    fn scroll(&self, wheel: Wheel, delta: i32);
+
+    /// Sends the actions together, with no other input between them
+    /// Dispatchers that can't do that send them one by one
+    fn send(&self, actions: &[Action]) {
+        for action in actions.iter() {
+            match *action {
+                Action::KeyDown(k) => self.key_down(k),
+                Action::KeyUp(k) => self.key_up(k),
+                Action::RelMouse { dx, dy } => self.rel_mouse(dx, dy),
+                Action::Scroll { wheel, delta } => self.scroll(wheel, delta),
+            }
+        }
+    }
+}
+
+/// Forwards to another Dispatcher, counting how many bindings hold each key
+/// The key goes up once the last of them lets go
+struct Tracked<T: Dispatcher> {
+    inner: Rc<T>,
+    policies: BTreeMap<Key, KeyPolicy>,
+    held: RefCell<BTreeMap<Key, usize>>,
+}
+
+impl<T: Dispatcher> Tracked<T> {
+    fn new(inner: Rc<T>, policies: BTreeMap<Key, KeyPolicy>) -> Self {
+        Tracked {
+            inner,
+            policies,
+            held: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Releases every key still held, all at once
+    /// Skipped if a panic interrupted a key_up or key_down
+    fn release_held(&self) {
+        let held = match self.held.try_borrow_mut() {
+            Ok(mut held) => std::mem::take(&mut *held),
+            Err(_) => return,
+        };
+        let releases: Vec<Action> = held.keys().map(|k| Action::KeyUp(*k)).collect();
+        if !releases.is_empty() {
+            self.inner.send(&releases);
+        }
+    }
+
+    /// Counts a press, returns what should reach the inner Dispatcher
+    fn press(&self, k: Key) -> Vec<Action> {
+        let count = {
+            let mut held = self.held.borrow_mut();
+            let count = held.entry(k).or_insert(0);
+            *count += 1;
+            *count
+        };
+
+        let policy = self.policies.get(&k).cloned().unwrap_or(KeyPolicy::Shared);
+        if count == 1 {
+            vec![Action::KeyDown(k)]
+        } else if policy == KeyPolicy::Retrigger {
+            vec![Action::KeyUp(k), Action::KeyDown(k)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Counts a release, true if it should reach the inner Dispatcher
+    fn release(&self, k: Key) -> bool {
+        let mut held = self.held.borrow_mut();
+        match held.get_mut(&k) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            Some(_) => {
+                held.remove(&k);
+                true
+            }
+            // Not pressed through this Manager, pass it on as before
+            None => true,
+        }
+    }
+}
+
+impl<T: Dispatcher> Dispatcher for Tracked<T> {
+    fn from_cfg(cfg: &Config) -> Self {
+        Tracked::new(Rc::new(T::from_cfg(cfg)), cfg.key_policies.clone())
+    }
+
+    fn key_up(&self, k: Key) {
+        if self.release(k) {
+            self.inner.key_up(k);
+        }
+    }
+
+    fn key_down(&self, k: Key) {
+        match self.press(k).as_slice() {
+            [] => (),
+            [_] => self.inner.key_down(k),
+            retrigger => self.inner.send(retrigger),
+        }
+    }
+
+    fn rel_mouse(&self, dx: i32, dy: i32) {
+        self.inner.rel_mouse(dx, dy);
+    }
+
+    fn scroll(&self, wheel: Wheel, delta: i32) {
+        self.inner.scroll(wheel, delta);
+    }
+
+    fn send(&self, actions: &[Action]) {
+        let passed: Vec<Action> = actions.iter()
+            .flat_map(|action| match *action {
+                Action::KeyDown(k) => self.press(k),
+                Action::KeyUp(k) if !self.release(k) => Vec::new(),
+                _ => vec![*action],
+            })
+            .collect();
+        if !passed.is_empty() {
+            self.inner.send(&passed);
+        }
+    }
 }
 
 pub type TriggerHandler = Box<dyn Fn() -> ()>;
+pub type TickHandler = Box<dyn FnMut(Instant) -> ()>;
+/// Called on entering the deadzone and on leaving it, in that order
+type DeadzoneHandlers = (TriggerHandler, TriggerHandler);
+/// See Manager::subscribe
+type Subscriber = Box<dyn FnMut(&Event)>;
+
+/// Status of a toggle binding
+pub struct ToggleStatus {
+    pub button: usize,
+    pub key: Key,
+    latched: Rc<Cell<bool>>,
+}
 
-pub struct Manager {
-    previous_state: Option<State>,
+impl ToggleStatus {
+    pub fn is_latched(&self) -> bool {
+        self.latched.get()
+    }
+}
+
+/// Handlers for one set of button and joystick bindings
+/// The base config and every layer each build one of these
+struct Bindings {
     button_up: Vec<TriggerHandler>,
     button_down: Vec<TriggerHandler>,
+    /// Buttons without a binding fall through to the layer below
+    bound: [bool; BUTTON_LEN],
+
+    /// None falls through to the layer below
+    stick: Option<Stick>,
 
+    /// Called on every step for time-driven actions (e.g. turbo)
+    tickers: Vec<TickHandler>,
+
+    /// Called by release_all() for outputs that outlive their button press
+    releasers: Vec<TriggerHandler>,
+}
+
+struct Stick {
     x_enter_deadzone_negative: TriggerHandler,
     y_enter_deadzone_negative: TriggerHandler,
     x_enter_deadzone_positive: TriggerHandler,
@@ -65,39 +248,87 @@ pub struct Manager {
     y_exit_deadzone_positive: TriggerHandler,
 
     axis_tracker: Box<dyn FnMut(f32, f32, f32) -> ()>,
+    /// Releases what the axis tracker holds, whichever zones the axes are in
+    stop: TriggerHandler,
 
     x_deadzone: f32,
     y_deadzone: f32,
+    /// Zones the x and y axes hold outputs for, so a zone is never pressed twice
+    held: Cell<[Zone; 2]>,
 }
 
-impl Manager {
+/// Output of a Keys axis that changes within a zone, e.g. pulses or stages
+struct AxisDriver {
+    update: Box<dyn FnMut(f32, Instant)>,
+    /// Releases whatever the driver holds
+    stop: Rc<dyn Fn()>,
+}
 
-    #[cfg(target_os = "windows")]
-    pub fn new(cfg: Config) -> Self {
-        #[cfg(target_os = "windows")]
-        use super::win_input as winput;
+/// How a layer from the config is switched on and off
+struct LayerTrigger {
+    name: String,
+    button: usize,
+    activation: LayerActivation,
+}
 
-        let dispatcher = Rc::new(winput::WinDispatch::from_cfg(&cfg));
+/// The binding engine, sends what a Config binds to each State's events
+/// Call step() with every State, and regularly even without new ones for time-driven bindings
+pub struct Manager {
+    previous_state: Option<State>,
+    events: Events,
+    subscribers: Vec<Subscriber>,
+
+    /// Index 0 is the base config, the rest are Config::layers in order
+    layers: Vec<Bindings>,
+    /// triggers[i] switches layers[i + 1]
+    triggers: Vec<LayerTrigger>,
+    /// Active layer indices, most recently activated last
+    active_layers: Vec<usize>,
+    /// Layer that handled each held button's press
+    /// Releases always go to the same layer, even if the layers changed since
+    pressed_by: [Option<usize>; BUTTON_LEN],
+
+    toggles: Vec<ToggleStatus>,
+
+    /// Releases whatever keys are still down, however they were pressed
+    release_held: TriggerHandler,
+
+    /// Called on every step with the throttle's position
+    throttle_tracker: Option<Box<dyn FnMut(f32)>>,
+
+    gestures: Recognizer,
+    /// gesture_actions[i] is fired by Config::gestures[i]
+    gesture_actions: Vec<GestureAction>,
+}
 
+impl Bindings {
+    fn new<T: 'static + Dispatcher>(
+        dispatcher: &Rc<T>,
+        buttons: &[Option<Binding>; BUTTON_LEN],
+        stick: Option<Stick>,
+        toggles: &mut Vec<ToggleStatus>,
+    ) -> Self {
         let mut button_up = Vec::new();
         let mut button_down = Vec::new();
-        for k in cfg.buttons.iter().cloned() {
-            match k {
-                Some(k) => {
-                    let d1 = dispatcher.clone();
-                    let c1 = move || {
-                        // println!("Key up: {:?}", k);
-                        d1.key_up(k);
-                    };
-
-                    let d2 = dispatcher.clone();
-                    let c2 = move || {
-                        // println!("Key down: {:?}", k);
-                        d2.key_down(k);
-                    };
-
-                    button_up.push(Box::new(c1) as TriggerHandler);
-                    button_down.push(Box::new(c2) as TriggerHandler);
+        let mut bound = [false; BUTTON_LEN];
+        let mut tickers = Vec::new();
+        let mut releasers = Vec::new();
+        for (i, b) in buttons.iter().cloned().enumerate() {
+            bound[i] = b.is_some();
+            match b {
+                Some(b) => {
+                    let handlers = BindingHandlers::new(dispatcher, b);
+                    button_up.push(handlers.up);
+                    button_down.push(handlers.down);
+                    tickers.extend(handlers.ticker);
+                    releasers.extend(handlers.releaser);
+                    if let Some((key, latched)) = handlers.latched {
+                        toggles.push(ToggleStatus {
+                            button: i,
+                            key,
+                            latched,
+                        });
+                    }
                 }
 
                 None => {
@@ -106,37 +337,171 @@ impl Manager {
                 }
             }
         }
-        match cfg.joystick {
-            Some(JoystickConfig::Keys {
-                x_axis,
-                y_axis
-            }) => {
-                let x_enter_deadzone_negative =
-                    handler!(UP => dispatcher, x_axis.negative);
-                let x_exit_deadzone_negative =
-                    handler!(DOWN => dispatcher, x_axis.negative);
 
-                let y_enter_deadzone_negative =
-                    handler!(UP => dispatcher, y_axis.negative);
-                let y_exit_deadzone_negative =
-                    handler!(DOWN => dispatcher, y_axis.negative);
+        Bindings {
+            button_up,
+            button_down,
+            bound,
+            stick,
+            tickers,
+            releasers,
+        }
+    }
+}
+
+/// Handlers for one Binding, pressed and released like a button
+struct BindingHandlers {
+    up: TriggerHandler,
+    down: TriggerHandler,
+    /// For time-driven actions (e.g. turbo)
+    ticker: Option<TickHandler>,
+    /// For outputs that outlive their press
+    releaser: Option<TriggerHandler>,
+    /// A toggle's key and latch, for status output
+    latched: Option<(Key, Rc<Cell<bool>>)>,
+}
+
+impl BindingHandlers {
+    fn new<T: 'static + Dispatcher>(dispatcher: &Rc<T>, binding: Binding) -> Self {
+        match binding {
+            Binding::Key(k) => {
+                let d1 = dispatcher.clone();
+                let c1 = move || {
+                    d1.key_up(k);
+                };
+
+                let d2 = dispatcher.clone();
+                let c2 = move || {
+                    d2.key_down(k);
+                };
+
+                BindingHandlers {
+                    up: Box::new(c1) as TriggerHandler,
+                    down: Box::new(c2) as TriggerHandler,
+                    ticker: None,
+                    releaser: None,
+                    latched: None,
+                }
+            }
+
+            Binding::Action(ButtonAction::Turbo(turbo)) => {
+                let turbo = Rc::new(RefCell::new(
+                    Turbo::new(dispatcher.clone(), turbo)
+                ));
+
+                let t1 = turbo.clone();
+                let c1 = move || t1.borrow_mut().release();
+
+                let t2 = turbo.clone();
+                let c2 = move || t2.borrow_mut().press(Instant::now());
+
+                BindingHandlers {
+                    up: Box::new(c1) as TriggerHandler,
+                    down: Box::new(c2) as TriggerHandler,
+                    ticker: Some(Box::new(move |now| turbo.borrow_mut().tick(now)) as TickHandler),
+                    releaser: None,
+                    latched: None,
+                }
+            }
+
+            Binding::Action(ButtonAction::Scroll(scroll)) => {
+                let scroll = Rc::new(RefCell::new(
+                    Scroll::new(dispatcher.clone(), scroll)
+                ));
+
+                let s1 = scroll.clone();
+                let c1 = move || s1.borrow_mut().release();
+
+                let s2 = scroll.clone();
+                let c2 = move || s2.borrow_mut().press(Instant::now());
+
+                BindingHandlers {
+                    up: Box::new(c1) as TriggerHandler,
+                    down: Box::new(c2) as TriggerHandler,
+                    ticker: Some(Box::new(move |now| scroll.borrow_mut().tick(now)) as TickHandler),
+                    releaser: None,
+                    latched: None,
+                }
+            }
+
+            Binding::Action(ButtonAction::Toggle(k)) => {
+                let toggle = Rc::new(Toggle::new(dispatcher.clone(), k));
+                let latched = toggle.latched();
 
-                let x_enter_deadzone_positive =
-                    handler!(UP => dispatcher, x_axis.positive);
-                let x_exit_deadzone_positive =
-                    handler!(DOWN => dispatcher, x_axis.positive);
+                let t1 = toggle.clone();
+                let c1 = move || t1.press();
 
-                let y_enter_deadzone_positive =
-                    handler!(UP => dispatcher, y_axis.positive);
-                let y_exit_deadzone_positive =
-                    handler!(DOWN => dispatcher, y_axis.positive);
+                BindingHandlers {
+                    up: handler!(NOP),
+                    down: Box::new(c1) as TriggerHandler,
+                    ticker: None,
+                    releaser: Some(Box::new(move || toggle.release()) as TriggerHandler),
+                    latched: Some((k, latched)),
+                }
+            }
+        }
+    }
+}
 
-                Manager {
-                    previous_state: None,
+/// A gesture's action, held for a while once the gesture is recognized
+struct GestureAction {
+    handlers: BindingHandlers,
+    hold: Duration,
+    release_at: Option<Instant>,
+}
 
-                    button_up,
-                    button_down,
+impl GestureAction {
+    fn fire(&mut self, now: Instant) {
+        if self.release_at.take().is_some() {
+            (self.handlers.up)();
+        }
+        (self.handlers.down)();
+        self.release_at = Some(now + self.hold);
+    }
 
+    fn tick(&mut self, now: Instant) {
+        if let Some(ref mut tick) = self.handlers.ticker {
+            tick(now);
+        }
+        if matches!(self.release_at, Some(at) if at <= now) {
+            self.release_at = None;
+            (self.handlers.up)();
+        }
+    }
+
+    fn release(&mut self) {
+        if self.release_at.take().is_some() {
+            (self.handlers.up)();
+        }
+        if let Some(ref release) = self.handlers.releaser {
+            release();
+        }
+    }
+}
+
+impl Stick {
+    fn new<T: 'static + Dispatcher>(
+        dispatcher: &Rc<T>,
+        joystick: Option<JoystickConfig>,
+    ) -> Self {
+        match joystick {
+            Some(JoystickConfig::Keys {
+                x_axis,
+                y_axis
+            }) => {
+                let mut x_driver = Stick::axis_driver(dispatcher, &x_axis);
+                let mut y_driver = Stick::axis_driver(dispatcher, &y_axis);
+
+                let (x_enter_deadzone_negative, x_exit_deadzone_negative) =
+                    Stick::key_handlers(dispatcher, x_axis.negative, &x_driver);
+                let (y_enter_deadzone_negative, y_exit_deadzone_negative) =
+                    Stick::key_handlers(dispatcher, y_axis.negative, &y_driver);
+                let (x_enter_deadzone_positive, x_exit_deadzone_positive) =
+                    Stick::key_handlers(dispatcher, x_axis.positive, &x_driver);
+                let (y_enter_deadzone_positive, y_exit_deadzone_positive) =
+                    Stick::key_handlers(dispatcher, y_axis.positive, &y_driver);
+
+                Stick {
                     x_enter_deadzone_negative,
                     y_enter_deadzone_negative,
 
@@ -149,11 +514,21 @@ impl Manager {
                     x_exit_deadzone_positive,
                     y_exit_deadzone_positive,
 
-                    // NOP
-                    axis_tracker: Box::new(|_, _, _| ()),
+                    // Pulsed and staged axes are driven on every step, the rest only by zone changes
+                    axis_tracker: Box::new(move |x, y, _| {
+                        let now = Instant::now();
+                        if let Some(ref mut driver) = x_driver {
+                            (driver.update)(x, now);
+                        }
+                        if let Some(ref mut driver) = y_driver {
+                            (driver.update)(y, now);
+                        }
+                    }),
+                    stop: handler!(NOP),
 
                     x_deadzone: x_axis.deadzone,
                     y_deadzone: y_axis.deadzone,
+                    held: Cell::new([Zone::Dead; 2]),
                 }
             }
 
@@ -162,12 +537,45 @@ impl Manager {
                 y_axis,
             }) => {
 
-                Manager {
-                    previous_state: None,
+                Stick {
+                    x_enter_deadzone_negative: handler!(NOP),
+                    y_enter_deadzone_negative: handler!(NOP),
+
+                    x_enter_deadzone_positive: handler!(NOP),
+                    y_enter_deadzone_positive: handler!(NOP),
 
-                    button_up,
-                    button_down,
+                    x_exit_deadzone_negative: handler!(NOP),
+                    y_exit_deadzone_negative: handler!(NOP),
 
+                    x_exit_deadzone_positive: handler!(NOP),
+                    y_exit_deadzone_positive: handler!(NOP),
+
+                    axis_tracker: {
+                        let mut x_handler = Manager::mouse_mode_handler(x_axis);
+                        let mut y_handler = Manager::mouse_mode_handler(y_axis);
+                        let dispatcher = dispatcher.clone();
+                        // One motion for both axes, so diagonals don't move in steps
+                        Box::new(move |x, y, _| {
+                            let (dx, dy) = (x_handler(x), y_handler(y));
+                            if dx != 0 || dy != 0 {
+                                dispatcher.rel_mouse(dx, dy);
+                            }
+                        })
+                    },
+                    stop: handler!(NOP),
+
+                    x_deadzone: x_axis.deadzone,
+                    y_deadzone: y_axis.deadzone,
+                    held: Cell::new([Zone::Dead; 2]),
+                }
+            }
+
+            Some(JoystickConfig::Scroll {
+                x_axis,
+                y_axis,
+            }) => {
+
+                Stick {
                     x_enter_deadzone_negative: handler!(NOP),
                     y_enter_deadzone_negative: handler!(NOP),
 
@@ -181,33 +589,61 @@ impl Manager {
                     y_exit_deadzone_positive: handler!(NOP),
 
                     axis_tracker: {
-                        let mut x_handler = Manager::mouse_mode_handler(
-                            dispatcher.clone(),
-                            Axis::X,
-                            x_axis.clone(),
-                        );
-                        let mut y_handler = Manager::mouse_mode_handler(
-                            dispatcher.clone(),
-                            Axis::Y,
-                            y_axis.clone(),
-                        );
+                        let mut x_handler = Manager::scroll_handler(x_axis);
+                        let mut y_handler = Manager::scroll_handler(y_axis);
+                        let dispatcher = dispatcher.clone();
                         Box::new(move |x, y, _| {
-                            x_handler(x);
-                            y_handler(y);
+                            let scrolls: Vec<Action> = [
+                                (Wheel::Horizontal, x_handler(x)),
+                                (Wheel::Vertical, y_handler(y)),
+                            ]
+                                .iter()
+                                .filter(|(_, delta)| *delta != 0)
+                                .map(|&(wheel, delta)| Action::Scroll { wheel, delta })
+                                .collect();
+                            if !scrolls.is_empty() {
+                                dispatcher.send(&scrolls);
+                            }
                         })
                     },
+                    stop: handler!(NOP),
 
                     x_deadzone: x_axis.deadzone,
                     y_deadzone: y_axis.deadzone,
+                    held: Cell::new([Zone::Dead; 2]),
                 }
             }
 
-            None => Manager {
-                previous_state: None,
+            Some(JoystickConfig::Directions(directions)) => {
+                let sectors = Rc::new(RefCell::new(
+                    Directions::new(dispatcher.clone(), &directions)
+                ));
+                let s1 = sectors.clone();
+
+                Stick {
+                    x_enter_deadzone_negative: handler!(NOP),
+                    y_enter_deadzone_negative: handler!(NOP),
+
+                    x_enter_deadzone_positive: handler!(NOP),
+                    y_enter_deadzone_positive: handler!(NOP),
+
+                    x_exit_deadzone_negative: handler!(NOP),
+                    y_exit_deadzone_negative: handler!(NOP),
+
+                    x_exit_deadzone_positive: handler!(NOP),
+                    y_exit_deadzone_positive: handler!(NOP),
 
-                button_up,
-                button_down,
+                    // The deadzone is a circle, so sectors are driven on every step rather than by zone changes
+                    axis_tracker: Box::new(move |x, y, _| s1.borrow_mut().update(x, y)),
+                    stop: Box::new(move || sectors.borrow_mut().stop()),
 
+                    x_deadzone: directions.deadzone,
+                    y_deadzone: directions.deadzone,
+                    held: Cell::new([Zone::Dead; 2]),
+                }
+            }
+
+            None => Stick {
                 x_enter_deadzone_negative: handler!(NOP),
                 y_enter_deadzone_negative: handler!(NOP),
 
@@ -221,18 +657,230 @@ impl Manager {
                 y_exit_deadzone_positive: handler!(NOP),
 
                 axis_tracker: Box::new(|_, _, _| ()),
+                stop: handler!(NOP),
 
                 x_deadzone: 2.0,
                 y_deadzone: 2.0,
+                held: Cell::new([Zone::Dead; 2]),
             }
         }
     }
 
-    fn mouse_mode_handler<T: 'static + Dispatcher>(
-        dispatcher: Rc<T>,
-        axis: Axis,
-        config: AxisMouseConfig,
-    ) -> Box<dyn FnMut(f32) -> ()> {
+    /// Drives a Keys axis with pwm or stages on every step, None holds plain keys by zone
+    fn axis_driver<T: 'static + Dispatcher>(
+        dispatcher: &Rc<T>,
+        axis: &AxisKeyConfig,
+    ) -> Option<AxisDriver> {
+        if let Some(pwm) = axis.pwm {
+            let pwm = Rc::new(RefCell::new(Pwm::new(dispatcher.clone(), axis, pwm)));
+            let p1 = pwm.clone();
+            return Some(AxisDriver {
+                update: Box::new(move |f, now| p1.borrow_mut().update(f, now)),
+                stop: Rc::new(move || pwm.borrow_mut().stop()),
+            });
+        }
+
+        if !axis.stages.is_empty() {
+            let stages = Rc::new(RefCell::new(Stages::new(dispatcher.clone(), axis)));
+            let s1 = stages.clone();
+            return Some(AxisDriver {
+                update: Box::new(move |f, _| s1.borrow_mut().update(f)),
+                stop: Rc::new(move || stages.borrow_mut().stop()),
+            });
+        }
+
+        None
+    }
+
+    /// Handlers for entering and leaving the deadzone on the key's side
+    /// A driven axis only needs stopping, its driver presses the keys
+    fn key_handlers<T: 'static + Dispatcher>(
+        dispatcher: &Rc<T>,
+        key: Key,
+        driver: &Option<AxisDriver>,
+    ) -> DeadzoneHandlers {
+        match driver {
+            Some(driver) => {
+                let stop = driver.stop.clone();
+                (Box::new(move || stop()) as TriggerHandler, handler!(NOP))
+            }
+            None => (handler!(UP => dispatcher, key), handler!(DOWN => dispatcher, key)),
+        }
+    }
+
+    fn deadzones(&self) -> (f32, f32) {
+        (self.x_deadzone, self.y_deadzone)
+    }
+
+    /// Moves what the axis holds to the zone, nothing if it already holds it
+    fn hold(&self, axis: Axis, zone: Zone) {
+        let i = match axis {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => return,
+        };
+        let mut held = self.held.get();
+        if held[i] == zone {
+            return;
+        }
+
+        self.leave(axis, held[i]);
+        self.enter(axis, zone);
+        held[i] = zone;
+        self.held.set(held);
+    }
+
+    /// Press what the axis holds in the zone
+    fn enter(&self, axis: Axis, zone: Zone) {
+        match (axis, zone) {
+            (Axis::X, Zone::Positive) => (self.x_exit_deadzone_positive)(),
+            (Axis::X, Zone::Negative) => (self.x_exit_deadzone_negative)(),
+            (Axis::Y, Zone::Positive) => (self.y_exit_deadzone_positive)(),
+            (Axis::Y, Zone::Negative) => (self.y_exit_deadzone_negative)(),
+            _ => (),
+        }
+    }
+
+    /// Release what the axis holds in the zone
+    fn leave(&self, axis: Axis, zone: Zone) {
+        match (axis, zone) {
+            (Axis::X, Zone::Positive) => (self.x_enter_deadzone_positive)(),
+            (Axis::X, Zone::Negative) => (self.x_enter_deadzone_negative)(),
+            (Axis::Y, Zone::Positive) => (self.y_enter_deadzone_positive)(),
+            (Axis::Y, Zone::Negative) => (self.y_enter_deadzone_negative)(),
+            _ => (),
+        }
+    }
+
+    /// Hold whatever this stick deflected as in s would, measured against its own deadzones
+    fn press(&self, s: &State) {
+        self.hold(Axis::X, Zone::of(s.x_axis, self.x_deadzone));
+        self.hold(Axis::Y, Zone::of(s.y_axis, self.y_deadzone));
+    }
+
+    /// Release whatever the stick is holding
+    fn release(&self) {
+        self.hold(Axis::X, Zone::Dead);
+        self.hold(Axis::Y, Zone::Dead);
+        (self.stop)();
+    }
+}
+
+impl Manager {
+
+    #[cfg(target_os = "windows")]
+    pub fn new(cfg: Config) -> Self {
+        #[cfg(target_os = "windows")]
+        use super::win_input as winput;
+
+        let dispatcher = Rc::new(winput::WinDispatch::from_cfg(&cfg));
+        Manager::with_dispatcher(cfg, dispatcher)
+    }
+
+    /// Sends input through any Dispatcher, e.g. one that records instead of sending
+    pub fn with_dispatcher<T: 'static + Dispatcher>(cfg: Config, dispatcher: Rc<T>) -> Self {
+        let dispatcher = Rc::new(Tracked::new(dispatcher, cfg.key_policies.clone()));
+        let mut toggles = Vec::new();
+        let mut layers = vec![
+            Bindings::new(
+                &dispatcher,
+                &cfg.buttons,
+                Some(Stick::new(&dispatcher, cfg.joystick.clone())),
+                &mut toggles,
+            )
+        ];
+        let mut triggers = Vec::new();
+        for layer in cfg.layers.iter() {
+            layers.push(Bindings::new(
+                &dispatcher,
+                &layer.buttons,
+                layer.joystick.clone().map(|j| Stick::new(&dispatcher, Some(j))),
+                &mut toggles,
+            ));
+            triggers.push(LayerTrigger {
+                name: layer.name.clone(),
+                button: layer.button.0,
+                activation: layer.activation,
+            });
+        }
+
+        let deadzones = layers[0].stick
+            .as_ref()
+            .map(|stick| stick.deadzones())
+            .expect("Base layer always has a joystick config");
+
+        Manager {
+            previous_state: None,
+            events: Events::new(deadzones),
+            subscribers: Vec::new(),
+
+            layers,
+            triggers,
+            active_layers: Vec::new(),
+            pressed_by: [None; BUTTON_LEN],
+
+            toggles,
+
+            release_held: {
+                let tracked = dispatcher.clone();
+                Box::new(move || tracked.release_held())
+            },
+
+            gestures: Recognizer::new(&cfg.gestures),
+            gesture_actions: cfg.gestures.iter()
+                .map(|g| GestureAction {
+                    handlers: BindingHandlers::new(&dispatcher, g.action),
+                    hold: Duration::from_secs_f32(g.hold),
+                    release_at: None,
+                })
+                .collect(),
+
+            throttle_tracker: cfg.throttle.map(|throttle| match throttle {
+                ThrottleConfig::Scroll { wheel, z_axis } => {
+                    let mut handler = Manager::scroll_handler(z_axis);
+                    let dispatcher = dispatcher.clone();
+                    Box::new(move |z| {
+                        let delta = handler(z);
+                        if delta != 0 {
+                            dispatcher.scroll(wheel, delta);
+                        }
+                    }) as Box<dyn FnMut(f32)>
+                }
+            }),
+        }
+    }
+
+    /// Wheel delta to send for one axis per step
+    /// Fractions of a delta carry over to the next step, as do fractions of a notch with whole_notches
+    fn scroll_handler(config: AxisScrollConfig) -> Box<dyn FnMut(f32) -> i32> {
+        let mut last: Option<Instant> = None;
+        let mut delta_acc = 0.0;
+        Box::new(move |f| {
+            let now = Instant::now();
+            // Capped, so a gap between calls (e.g. while another layer had the stick) is not scrolled all at once
+            let elapsed = last.map_or(0.0, |last| now.duration_since(last).as_secs_f32())
+                .min(MAX_SCROLL_STEP);
+            last = Some(now);
+
+            if f.abs() <= config.deadzone {
+                delta_acc = 0.0;
+                return 0;
+            }
+
+            let deflection = (f.abs() - config.deadzone) / (1.0 - config.deadzone);
+            let direction = if config.invert { -f.signum() } else { f.signum() };
+            let notches_per_second = config.rate * deflection.min(1.0).powf(config.exponent);
+            delta_acc += direction * notches_per_second * elapsed * WHEEL_DELTA as f32;
+
+            let step = if config.whole_notches { WHEEL_DELTA as f32 } else { 1.0 };
+            let delta = (delta_acc / step).trunc() * step;
+            delta_acc -= delta;
+            delta as i32
+        })
+    }
+
+    /// Pixels to move the mouse along one axis per step
+    fn mouse_mode_handler(config: AxisMouseConfig) -> Box<dyn FnMut(f32) -> i32> {
         // NOTE: input per axis comes from a State
         //   Thus, normalized [-1.0, 1.0]
         //   Map directly to an 'inch'
@@ -241,7 +889,7 @@ impl Manager {
                 let mut dots_moved_acc = 0.0;
                 Box::new(move |f| {
                     if f.abs() < config.deadzone {
-                        return;
+                        return 0;
                     }
 
                     let inches_moved = f;
@@ -251,13 +899,9 @@ impl Manager {
                     // Apply dots-per-pixel-function:
                     //   g(f) = c
                     let pixels_moved = (dots_moved_acc / dots_per_pixel) as i32;
-                    dots_moved_acc = dots_moved_acc % dots_per_pixel;
+                    dots_moved_acc %= dots_per_pixel;
 
-                    match axis {
-                        Axis::X => dispatcher.rel_mouse_x(pixels_moved),
-                        Axis::Y => dispatcher.rel_mouse_y(pixels_moved),
-                        Axis::Z => panic!("Cannot have mouse Z axis movement"),
-                    }
+                    pixels_moved
                 })
             },
 
@@ -265,7 +909,7 @@ impl Manager {
                 let mut dots_moved_acc = 0.0;
                 Box::new(move |f| {
                     if f.abs() < config.deadzone {
-                        return;
+                        return 0;
                     }
 
                     let inches_moved = f;
@@ -278,13 +922,9 @@ impl Manager {
                     let dots_per_pixel = (1.0 - f.abs()) * m + bias;
 
                     let pixels_moved =  (dots_moved_acc / dots_per_pixel) as i32;
-                    dots_moved_acc = dots_moved_acc % dots_per_pixel;
+                    dots_moved_acc %= dots_per_pixel;
 
-                    match axis {
-                        Axis::X => dispatcher.rel_mouse_x(pixels_moved),
-                        Axis::Y => dispatcher.rel_mouse_y(pixels_moved),
-                        Axis::Z => panic!("Cannot have mouse Z axis movement"),
-                    }
+                    pixels_moved
                 })
             },
 
@@ -292,7 +932,7 @@ impl Manager {
                 let mut dots_moved_acc = 0.0;
                 Box::new(move |f| {
                     if f.abs() < config.deadzone {
-                        return;
+                        return 0;
                     }
 
                     let inches_moved = f;
@@ -314,139 +954,214 @@ impl Manager {
                         dots_per_pixel = min * dots_per_pixel.signum();
                     }
 
-                    let pixels_moved = if dots_per_pixel != 0.0 {
+                    if dots_per_pixel != 0.0 {
                         let pixels_moved = (dots_moved_acc / dots_per_pixel) as i32;
-                        dots_moved_acc = dots_moved_acc % dots_per_pixel;
+                        dots_moved_acc %= dots_per_pixel;
                         pixels_moved
                     } else {
                         0
-                    };
-
-                    match axis {
-                        Axis::X => dispatcher.rel_mouse_x(pixels_moved),
-                        Axis::Y => dispatcher.rel_mouse_y(pixels_moved),
-                        Axis::Z => panic!("Cannot have mouse Z axis movement"),
                     }
                 })
             },
         }
     }
 
-    #[allow(dead_code)]
-    pub fn dbg() -> Self {
-        Manager {
-            previous_state: None,
-            button_up: vec![
-                printHandler!("up button1"),
-                printHandler!("up button2"),
-                printHandler!("up button3"),
-                printHandler!("up button4"),
-                printHandler!("up button5"),
-                printHandler!("up button6"),
-                printHandler!("up button7"),
-                printHandler!("up button8"),
-                printHandler!("up button9"),
-                printHandler!("up button10"),
-                printHandler!("up button11"),
-            ],
-            button_down: vec![
-                printHandler!("down button1"),
-                printHandler!("down button2"),
-                printHandler!("down button3"),
-                printHandler!("down button4"),
-                printHandler!("down button5"),
-                printHandler!("down button6"),
-                printHandler!("down button7"),
-                printHandler!("down button8"),
-                printHandler!("down button9"),
-                printHandler!("down button10"),
-                printHandler!("down button11"),
-            ],
-            x_enter_deadzone_negative: printHandler!("x-axis enter deadzone -"),
-            y_enter_deadzone_negative: printHandler!("y-axis enter deadzone -"),
-
-            x_enter_deadzone_positive: printHandler!("x-axis enter deadzone +"),
-            y_enter_deadzone_positive: printHandler!("y-axis enter deadzone +"),
-
-            x_exit_deadzone_negative: printHandler!("x-axis exit deadzone -"),
-            y_exit_deadzone_negative: printHandler!("y-axis exit deadzone -"),
-
-            x_exit_deadzone_positive: printHandler!("x-axis exit deadzone +"),
-            y_exit_deadzone_positive: printHandler!("y-axis exit deadzone +"),
-
-            // NOP
-            axis_tracker: Box::new(|_, _, _| ()),
-
-            x_deadzone: 0.5,
-            y_deadzone: 0.5,
+    pub fn step(&mut self, ns: State) {
+        let events = self.events.next(&ns);
+        for event in events.iter() {
+            for subscriber in self.subscribers.iter_mut() {
+                subscriber(event);
+            }
         }
-    }
 
-    pub fn step(&mut self, ns: State) {
-        match self.previous_state.take() {
-            Some(ps) => {
-                for i in 0..BUTTON_LEN {
-                    let pb = ps.buttons[i];
-                    let nb = ns.buttons[i];
-
-                    // println!("{}: {} -> {}", i + 1, pb, nb);
-
-                    if !pb && nb {
-                        self.button_down[i]();
-                    } else if pb && !nb {
-                        self.button_up[i]();
-                    }
-                }
+        if let Some(ps) = self.previous_state.take() {
+            for event in events {
+                self.handle(event, &ps);
+            }
+            // Events from a step that switched sticks were measured against the old stick's deadzones
+            self.stick_mut().press(&ns);
+
+            // Mouse and scroll bindings move on every step, not just when the stick moves
+            (self.stick_mut().axis_tracker)(ns.x_axis, ns.y_axis, 0.0);
+            if let Some(ref mut throttle) = self.throttle_tracker {
+                throttle(ns.z_axis);
+            }
+        }
 
-                // TODO: z-axis support
-                let ps_x = ps.x_axis.abs();
-                let ps_y = ps.y_axis.abs();
-                // let ps_z = ps.z_axis.abs();
+        let now = Instant::now();
+        for i in self.gestures.next(now, &ns) {
+            self.gesture_actions[i].fire(now);
+        }
+        self.previous_state = Some(ns);
 
-                let ns_x = ns.x_axis.abs();
-                let ns_y = ns.y_axis.abs();
-                // let ns_z = ns.z_axis.abs();
+        for layer in self.layers.iter_mut() {
+            for tick in layer.tickers.iter_mut() {
+                tick(now);
+            }
+        }
+        for action in self.gesture_actions.iter_mut() {
+            action.tick(now);
+        }
+    }
 
-                (self.axis_tracker)(ns.x_axis, ns.y_axis, 0.0);
+    /// Calls f with every event from now on, before the bindings handle it
+    pub fn subscribe<F: 'static + FnMut(&Event)>(&mut self, f: F) {
+        self.subscribers.push(Box::new(f));
+    }
 
-                if ps_x > self.x_deadzone && ns_x <= self.x_deadzone {
-                    if ns.x_axis >= 0.0 {
-                        (self.x_enter_deadzone_positive)();
-                    } else {
-                        (self.x_enter_deadzone_negative)();
-                    }
-                } else if ps_x <= self.x_deadzone && ns_x > self.x_deadzone {
-                    if ns.x_axis >= 0.0 {
-                        (self.x_exit_deadzone_positive)();
-                    } else {
-                        (self.x_exit_deadzone_negative)();
-                    }
-                }
+    /// Maps an event to the bindings of the active layers
+    /// ps is the State before the event's
+    fn handle(&mut self, event: Event, ps: &State) {
+        match event {
+            Event::ButtonPressed(b) => self.button_down(b.0, ps),
+            Event::ButtonReleased(b) => self.button_up(b.0, ps),
+            Event::AxisZoneChanged { axis, to, .. } => self.stick_mut().hold(axis, to),
+            Event::DeviceConnected | Event::AxisMoved { .. } => (),
+        }
+    }
 
-                if ps_y > self.y_deadzone && ns_y <= self.y_deadzone {
-                    if ns.y_axis >= 0.0 {
-                        (self.y_enter_deadzone_positive)();
+    fn button_down(&mut self, button: usize, ps: &State) {
+        if let Some(t) = self.trigger_for(button) {
+            let layer = t + 1;
+            match self.triggers[t].activation {
+                LayerActivation::Hold => self.activate_layer(layer, ps),
+                LayerActivation::Toggle => {
+                    if self.active_layers.contains(&layer) {
+                        self.deactivate_layer(layer, ps);
                     } else {
-                        (self.y_enter_deadzone_negative)();
-                    }
-                } else if ps_y <= self.y_deadzone && ns_y > self.y_deadzone {
-                    if ns.y_axis >= 0.0 {
-                        (self.y_exit_deadzone_positive)();
-                    } else {
-                        (self.y_exit_deadzone_negative)();
+                        self.activate_layer(layer, ps);
                     }
                 }
-                self.previous_state = Some(ns);
             }
+            return;
+        }
 
-            None => {
-                self.previous_state = Some(ns);
+        let layer = self.button_layer(button);
+        (self.layers[layer].button_down[button])();
+        self.pressed_by[button] = Some(layer);
+    }
+
+    fn button_up(&mut self, button: usize, ps: &State) {
+        if let Some(t) = self.trigger_for(button) {
+            if self.triggers[t].activation == LayerActivation::Hold {
+                self.deactivate_layer(t + 1, ps);
             }
+            return;
+        }
+
+        if let Some(layer) = self.pressed_by[button].take() {
+            (self.layers[layer].button_up[button])();
         }
     }
+
+    fn trigger_for(&self, button: usize) -> Option<usize> {
+        self.triggers.iter().position(|t| t.button == button)
+    }
+
+    /// Topmost active layer that binds the button
+    fn button_layer(&self, button: usize) -> usize {
+        self.active_layers.iter()
+            .rev()
+            .cloned()
+            .find(|l| self.layers[*l].bound[button])
+            .unwrap_or(0)
+    }
+
+    /// Topmost active layer with a joystick config
+    fn stick_layer(&self) -> usize {
+        self.active_layers.iter()
+            .rev()
+            .cloned()
+            .find(|l| self.layers[*l].stick.is_some())
+            .unwrap_or(0)
+    }
+
+    fn stick_mut(&mut self) -> &mut Stick {
+        let layer = self.stick_layer();
+        self.layers[layer].stick
+            .as_mut()
+            .expect("Stick layer always has a joystick config")
+    }
+
+    fn activate_layer(&mut self, layer: usize, ps: &State) {
+        let old_stick = self.stick_layer();
+        self.active_layers.retain(|l| *l != layer);
+        self.active_layers.push(layer);
+        self.hand_off_stick(old_stick, ps);
+    }
+
+    fn deactivate_layer(&mut self, layer: usize, ps: &State) {
+        let old_stick = self.stick_layer();
+        self.active_layers.retain(|l| *l != layer);
+        self.hand_off_stick(old_stick, ps);
+    }
+
+    /// Moves held stick outputs from the old stick layer to the current one
+    fn hand_off_stick(&mut self, old_stick: usize, ps: &State) {
+        let new_stick = self.stick_layer();
+        if old_stick == new_stick {
+            return;
+        }
+
+        if let Some(ref stick) = self.layers[old_stick].stick {
+            stick.release();
+        }
+        if let Some(ref stick) = self.layers[new_stick].stick {
+            stick.press(ps);
+            self.events.set_deadzones(stick.deadzones());
+        }
+    }
+
+    /// Release everything held by the last seen state and any latched outputs
+    /// Used when the device goes away so no key is left pressed
+    pub fn release_all(&mut self) {
+        for (i, pressed_by) in self.pressed_by.iter_mut().enumerate() {
+            if let Some(layer) = pressed_by.take() {
+                (self.layers[layer].button_up[i])();
+            }
+        }
+
+        self.previous_state = None;
+        self.stick_mut().release();
+        self.active_layers.clear();
+
+        for layer in self.layers.iter() {
+            for release in layer.releasers.iter() {
+                release();
+            }
+        }
+        for action in self.gesture_actions.iter_mut() {
+            action.release();
+        }
+        self.gestures.reset();
+
+        // Anything the bindings missed, e.g. a turbo caught mid-press
+        (self.release_held)();
+    }
+
+    pub fn toggles(&self) -> &[ToggleStatus] {
+        &self.toggles
+    }
+
+    /// Name of the most recently activated layer, if any
+    pub fn active_layer(&self) -> Option<&str> {
+        self.active_layers.last()
+            .map(|l| self.triggers[*l - 1].name.as_str())
+    }
 }
 
+/// No key outlives its Manager, whether it was replaced, returned from or unwound by a panic
+/// This is how panics release keys, rather than a panic hook: the hook runs before
+///   unwinding, while the Manager may still be borrowed by the code that panicked
+impl Drop for Manager {
+    fn drop(&mut self) {
+        (self.release_held)();
+    }
+}
+
+
 
+/// A Packet normalized against the stick's resting position
 #[derive(Debug, Clone, PartialEq)]
 pub struct State {
     buttons: [bool; 11],
@@ -461,7 +1176,7 @@ pub struct State {
 impl fmt::Display for State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for (i, b) in self.buttons.iter().enumerate() {
-            write!(f, "button {}: {}\n", i + 1, b)?;
+            write!(f, "{}: {}\n", Button(i), b)?;
         }
 
         write!(f, "x-axis: {}\n", self.x_axis)?;
@@ -474,6 +1189,22 @@ impl fmt::Display for State {
 }
 
 impl State {
+    pub fn buttons(&self) -> &[bool; BUTTON_LEN] {
+        &self.buttons
+    }
+
+    pub fn x_axis(&self) -> f32 {
+        self.x_axis
+    }
+
+    pub fn y_axis(&self) -> f32 {
+        self.y_axis
+    }
+
+    pub fn z_axis(&self) -> f32 {
+        self.z_axis
+    }
+
     /// Normalize axis values between [-1, 1]
     /// x-axis:
     ///   * +1 => right
@@ -504,3 +1235,245 @@ impl State {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::record::RecordDispatch;
+
+    /// Turbo on the trigger, a plain key on thumb_up and WASD on the stick
+    fn config() -> Config {
+        serde_json::from_value(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": {
+                "trigger": { "Turbo": { "key": "Enter", "rate": 10.0, "duty_cycle": 0.5 } },
+                "thumb_up": "E"
+            },
+            "joystick": {
+                "Keys": {
+                    "x_axis": { "positive": "D", "negative": "A", "deadzone": 0.2 },
+                    "y_axis": { "positive": "W", "negative": "S", "deadzone": 0.2 }
+                }
+            }
+        })).unwrap()
+    }
+
+    fn state(buttons: &[usize], y_axis: u8) -> State {
+        let mut pressed = [false; BUTTON_LEN];
+        for b in buttons.iter() {
+            pressed[*b] = true;
+        }
+        State::from_packet([0x80, 0x80], Packet {
+            buttons: pressed,
+            x_axis: 0x80,
+            y_axis,
+            z_axis: 0x80,
+        })
+    }
+
+    /// Keys pressed and not yet released by the recorded actions
+    fn held(recorder: &RecordDispatch, held: &mut BTreeSet<Key>) {
+        for (_, action) in recorder.take() {
+            match action {
+                Action::KeyDown(k) => { held.insert(k); }
+                Action::KeyUp(k) => { held.remove(&k); }
+                _ => (),
+            }
+        }
+    }
+
+    /// A Manager holding the turbo, thumb_up and stick forward keys
+    fn holding(recorder: &Rc<RecordDispatch>, keys: &mut BTreeSet<Key>) -> Manager {
+        let mut manager = Manager::with_dispatcher(config(), recorder.clone());
+        manager.step(state(&[], 0x80));
+        manager.step(state(&[0, 2], 0x00));
+
+        held(recorder, keys);
+        let expected: BTreeSet<Key> = [Key::Enter, Key::E, Key::W].iter().cloned().collect();
+        assert_eq!(*keys, expected);
+        manager
+    }
+
+    #[test]
+    fn release_all_releases_held_keys() {
+        let recorder = Rc::new(RecordDispatch::new());
+        let mut keys = BTreeSet::new();
+        let mut manager = holding(&recorder, &mut keys);
+
+        manager.release_all();
+        held(&recorder, &mut keys);
+        assert!(keys.is_empty(), "still held: {:?}", keys);
+    }
+
+    #[test]
+    fn drop_releases_held_keys() {
+        let recorder = Rc::new(RecordDispatch::new());
+        let mut keys = BTreeSet::new();
+        let manager = holding(&recorder, &mut keys);
+
+        drop(manager);
+        held(&recorder, &mut keys);
+        assert!(keys.is_empty(), "still held: {:?}", keys);
+    }
+
+    #[test]
+    fn scroll_after_a_gap() {
+        let config: AxisScrollConfig = serde_json::from_value(json!({
+            "rate": 10.0,
+            "deadzone": 0.2
+        })).unwrap();
+        let mut handler = Manager::scroll_handler(config);
+        handler(1.0);
+        std::thread::sleep(Duration::from_millis(500));
+
+        // 5 notches had the whole gap been scrolled
+        let delta = handler(1.0);
+        assert!(delta > 0 && delta <= (10.0 * MAX_SCROLL_STEP * WHEEL_DELTA as f32) as i32, "{}", delta);
+    }
+
+    #[test]
+    fn panic_releases_held_keys() {
+        let recorder = Rc::new(RecordDispatch::new());
+        let mut keys = BTreeSet::new();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _manager = holding(&recorder, &mut keys);
+            panic!("unwinding with keys held");
+        }));
+        assert!(result.is_err());
+
+        held(&recorder, &mut keys);
+        assert!(keys.is_empty(), "still held: {:?}", keys);
+    }
+
+    #[test]
+    fn stick_hand_off_between_deadzones() {
+        let cfg: Config = serde_json::from_value(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": {},
+            "joystick": {
+                "Keys": {
+                    "x_axis": { "positive": "D", "negative": "A", "deadzone": 0.5 },
+                    "y_axis": { "positive": "W", "negative": "S", "deadzone": 0.5 }
+                }
+            },
+            "layers": [{
+                "name": "fine",
+                "button": "base_right_back",
+                "activation": "Hold",
+                "buttons": {},
+                "joystick": {
+                    "Keys": {
+                        "x_axis": { "positive": "D", "negative": "A", "deadzone": 0.1 },
+                        "y_axis": { "positive": "W", "negative": "S", "deadzone": 0.1 }
+                    }
+                }
+            }]
+        })).unwrap();
+        let recorder = Rc::new(RecordDispatch::new());
+        let mut manager = Manager::with_dispatcher(cfg, recorder.clone());
+        let mut keys = BTreeSet::new();
+
+        // Past the layer's deadzone but not the base one's, then further while switching
+        manager.step(state(&[], 0x80));
+        manager.step(state(&[], 0x5A));
+        manager.step(state(&[9], 0x1A));
+        held(&recorder, &mut keys);
+        assert_eq!(keys.iter().collect::<Vec<_>>(), vec![&Key::W]);
+
+        manager.step(state(&[], 0x1A));
+        manager.step(state(&[], 0x80));
+        held(&recorder, &mut keys);
+        assert!(keys.is_empty(), "still held: {:?}", keys);
+
+        // Between the deadzones after switching back only the layer's holds
+        manager.step(state(&[9], 0x5A));
+        held(&recorder, &mut keys);
+        assert_eq!(keys.iter().collect::<Vec<_>>(), vec![&Key::W]);
+        manager.step(state(&[], 0x5A));
+        held(&recorder, &mut keys);
+        assert!(keys.is_empty(), "still held: {:?}", keys);
+    }
+
+    /// The actions recorded since the last call
+    fn actions(recorder: &RecordDispatch) -> Vec<Action> {
+        recorder.take().into_iter().map(|(_, a)| a).collect()
+    }
+
+    /// E on the trigger and on thumb_up, bound as given
+    fn sharing(trigger: serde_json::Value, thumb_up: serde_json::Value, policy: &str) -> (Manager, Rc<RecordDispatch>) {
+        let cfg: Config = serde_json::from_value(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": { "trigger": trigger, "thumb_up": thumb_up },
+            "key_policies": { "E": policy }
+        })).unwrap();
+        let recorder = Rc::new(RecordDispatch::new());
+        let mut manager = Manager::with_dispatcher(cfg, recorder.clone());
+        manager.step(state(&[], 0x80));
+        (manager, recorder)
+    }
+
+    #[test]
+    fn shared_key_released_by_last_button() {
+        let (mut manager, recorder) = sharing(json!("E"), json!("E"), "Shared");
+        manager.step(state(&[0], 0x80));
+        manager.step(state(&[0, 2], 0x80));
+        assert_eq!(actions(&recorder), vec![Action::KeyDown(Key::E)]);
+
+        manager.step(state(&[2], 0x80));
+        assert_eq!(actions(&recorder), vec![]);
+        manager.step(state(&[], 0x80));
+        assert_eq!(actions(&recorder), vec![Action::KeyUp(Key::E)]);
+    }
+
+    #[test]
+    fn retrigger_presses_again() {
+        let (mut manager, recorder) = sharing(json!("E"), json!("E"), "Retrigger");
+        manager.step(state(&[0], 0x80));
+        assert_eq!(actions(&recorder), vec![Action::KeyDown(Key::E)]);
+        manager.step(state(&[0, 2], 0x80));
+        assert_eq!(actions(&recorder), vec![Action::KeyUp(Key::E), Action::KeyDown(Key::E)]);
+
+        manager.step(state(&[2], 0x80));
+        assert_eq!(actions(&recorder), vec![]);
+        manager.step(state(&[], 0x80));
+        assert_eq!(actions(&recorder), vec![Action::KeyUp(Key::E)]);
+    }
+
+    #[test]
+    fn toggle_and_turbo_share_a_key() {
+        let turbo = json!({ "Turbo": { "key": "E", "rate": 2.0, "duty_cycle": 0.5 } });
+        let (mut manager, recorder) = sharing(json!({ "Toggle": "E" }), turbo, "Shared");
+
+        // Latched by the toggle, the turbo's pulse changes nothing
+        manager.step(state(&[0], 0x80));
+        manager.step(state(&[], 0x80));
+        assert_eq!(actions(&recorder), vec![Action::KeyDown(Key::E)]);
+        manager.step(state(&[2], 0x80));
+        manager.step(state(&[], 0x80));
+        assert_eq!(actions(&recorder), vec![]);
+
+        manager.step(state(&[0], 0x80));
+        assert_eq!(actions(&recorder), vec![Action::KeyUp(Key::E)]);
+
+        // Unlatched while the turbo holds it, the key stays down until the turbo lets go
+        manager.step(state(&[], 0x80));
+        manager.step(state(&[2], 0x80));
+        manager.step(state(&[0, 2], 0x80));
+        manager.step(state(&[2], 0x80));
+        assert_eq!(actions(&recorder), vec![Action::KeyDown(Key::E)]);
+        manager.step(state(&[0, 2], 0x80));
+        assert_eq!(actions(&recorder), vec![]);
+        manager.step(state(&[], 0x80));
+        assert_eq!(actions(&recorder), vec![Action::KeyUp(Key::E)]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+
+use super::config::{Axis, Button, BUTTON_LEN};
+use super::dispatch::State;
+
+/// Which side of its deadzone an axis is on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Zone {
+    Negative,
+    Dead,
+    Positive,
+}
+
+impl Zone {
+    pub fn of(value: f32, deadzone: f32) -> Zone {
+        if value.abs() <= deadzone {
+            Zone::Dead
+        } else if value >= 0.0 {
+            Zone::Positive
+        } else {
+            Zone::Negative
+        }
+    }
+}
+
+/// A change between two States
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// The first State, read once the stick was zeroed
+    DeviceConnected,
+    ButtonPressed(Button),
+    ButtonReleased(Button),
+    AxisZoneChanged { axis: Axis, from: Zone, to: Zone },
+    AxisMoved { axis: Axis, value: f32 },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::DeviceConnected => write!(f, "device_connected"),
+            Event::ButtonPressed(b) => write!(f, "button_pressed({})", b.name()),
+            Event::ButtonReleased(b) => write!(f, "button_released({})", b.name()),
+            Event::AxisZoneChanged { axis, from, to } => write!(f, "axis_zone_changed({:?}, {:?} -> {:?})", axis, from, to),
+            Event::AxisMoved { axis, value } => write!(f, "axis_moved({:?}, {:+.2})", axis, value),
+        }
+    }
+}
+
+/// Turns successive States into Events
+/// Button events come before axis events
+pub struct Events {
+    previous: Option<State>,
+    /// Deadzones of the x and y axes, zones are measured against these
+    deadzones: (f32, f32),
+}
+
+impl Events {
+    pub fn new(deadzones: (f32, f32)) -> Self {
+        Events {
+            previous: None,
+            deadzones,
+        }
+    }
+
+    /// Later zone changes are measured against the new deadzones, without events for the switch
+    pub fn set_deadzones(&mut self, deadzones: (f32, f32)) {
+        self.deadzones = deadzones;
+    }
+
+    pub fn next(&mut self, ns: &State) -> Vec<Event> {
+        let ps = match self.previous.replace(ns.clone()) {
+            Some(ps) => ps,
+            None => return vec![Event::DeviceConnected],
+        };
+
+        let mut events = Vec::new();
+        for i in 0..BUTTON_LEN {
+            match (ps.buttons()[i], ns.buttons()[i]) {
+                (false, true) => events.push(Event::ButtonPressed(Button(i))),
+                (true, false) => events.push(Event::ButtonReleased(Button(i))),
+                _ => (),
+            }
+        }
+
+        let (x_deadzone, y_deadzone) = self.deadzones;
+        let axes = [
+            (Axis::X, ps.x_axis(), ns.x_axis(), Some(x_deadzone)),
+            (Axis::Y, ps.y_axis(), ns.y_axis(), Some(y_deadzone)),
+            (Axis::Z, ps.z_axis(), ns.z_axis(), None),
+        ];
+        for (axis, from, to, deadzone) in axes.iter().cloned() {
+            if let Some(deadzone) = deadzone {
+                let (from, to) = (Zone::of(from, deadzone), Zone::of(to, deadzone));
+                if from != to {
+                    events.push(Event::AxisZoneChanged { axis, from, to });
+                }
+            }
+        }
+        for (axis, from, to, _) in axes.iter().cloned() {
+            if from != to {
+                events.push(Event::AxisMoved { axis, value: to });
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw_input::Packet;
+
+    /// Buttons pressed and the x axis at its raw value, the other axes centered
+    fn state(buttons: &[usize], x_axis: u8) -> State {
+        let mut pressed = [false; BUTTON_LEN];
+        for b in buttons.iter() {
+            pressed[*b] = true;
+        }
+        State::from_packet([0x80, 0x80], Packet {
+            buttons: pressed,
+            x_axis,
+            y_axis: 0x80,
+            z_axis: 0x80,
+        })
+    }
+
+    #[test]
+    fn device_connected_first() {
+        let mut events = Events::new((0.2, 0.2));
+        assert_eq!(events.next(&state(&[0], 0x80)), vec![Event::DeviceConnected]);
+        assert_eq!(events.next(&state(&[0], 0x80)), vec![]);
+    }
+
+    #[test]
+    fn button_edges() {
+        let mut events = Events::new((0.2, 0.2));
+        events.next(&state(&[], 0x80));
+        assert_eq!(events.next(&state(&[0, 3], 0x80)),
+            vec![Event::ButtonPressed(Button(0)), Event::ButtonPressed(Button(3))]);
+        assert_eq!(events.next(&state(&[0, 3], 0x80)), vec![]);
+        assert_eq!(events.next(&state(&[3, 5], 0x80)),
+            vec![Event::ButtonReleased(Button(0)), Event::ButtonPressed(Button(5))]);
+    }
+
+    #[test]
+    fn zone_changes_on_crossing_the_deadzone() {
+        let mut events = Events::new((0.5, 0.5));
+        events.next(&state(&[], 0x80));
+
+        // Within the deadzone it only moves
+        let inside = state(&[], 0xA0);
+        assert_eq!(events.next(&inside), vec![Event::AxisMoved { axis: Axis::X, value: inside.x_axis() }]);
+        assert_eq!(events.next(&inside), vec![]);
+
+        let outside = state(&[], 0xF0);
+        assert_eq!(events.next(&outside), vec![
+            Event::AxisZoneChanged { axis: Axis::X, from: Zone::Dead, to: Zone::Positive },
+            Event::AxisMoved { axis: Axis::X, value: outside.x_axis() },
+        ]);
+
+        let across = state(&[0], 0x00);
+        assert_eq!(events.next(&across), vec![
+            Event::ButtonPressed(Button(0)),
+            Event::AxisZoneChanged { axis: Axis::X, from: Zone::Positive, to: Zone::Negative },
+            Event::AxisMoved { axis: Axis::X, value: across.x_axis() },
+        ]);
+    }
+
+    #[test]
+    fn set_deadzones_rezones() {
+        let mut events = Events::new((0.5, 0.5));
+        events.next(&state(&[], 0x80));
+        events.next(&state(&[], 0xA0));
+
+        // No event for the switch itself, later moves are measured against the new deadzone
+        events.set_deadzones((0.1, 0.1));
+        assert_eq!(events.next(&state(&[], 0xA0)), vec![]);
+        let back = state(&[], 0x80);
+        assert_eq!(events.next(&back), vec![
+            Event::AxisZoneChanged { axis: Axis::X, from: Zone::Positive, to: Zone::Dead },
+            Event::AxisMoved { axis: Axis::X, value: back.x_axis() },
+        ]);
+    }
+}
@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::IgnoredAny;
+
+use super::config::{Config, ProfileSet};
+
+/// Config file formats, chosen by file extension
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+    Ron,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Format, String> {
+        let ext = path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match ext.as_deref() {
+            Some("json") => Ok(Format::Json),
+            Some("toml") => Ok(Format::Toml),
+            Some("yaml") | Some("yml") => Ok(Format::Yaml),
+            Some("ron") => Ok(Format::Ron),
+            _ => Err(format!(
+                "Unknown config format for '{}' (expected .json, .toml, .yaml, .yml or .ron)",
+                path.display()
+            )),
+        }
+    }
+
+    /// Parses a document into JSON's data model, which the rest of config loading works on
+    pub fn parse(self, text: &str) -> Result<serde_json::Value, String> {
+        match self {
+            Format::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            Format::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+            Format::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+
+            // NOTE: RON cannot be read into a serde_json::Value
+            //   Enum variant names (e.g. `Constant(2100.0)`) are dropped by deserialize_any
+            //   Read the actual config types instead and convert those
+            Format::Ron => {
+                let fields = ron::de::from_str::<RonFields>(text).unwrap_or_default();
+                if fields.extends.0 {
+                    return Err("'extends' is only supported in JSON, TOML and YAML files".to_string());
+                }
+
+                // Whichever layout parses, or the error of the one the fields point to
+                let value = match ron::de::from_str::<ProfileSet>(text) {
+                    Ok(set) => serde_json::to_value(set),
+                    Err(e) if fields.profiles.0 => return Err(e.to_string()),
+                    Err(_) => ron::de::from_str::<Config>(text)
+                        .map_err(|e| e.to_string())
+                        .map(serde_json::to_value)?,
+                };
+
+                value.map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// Writes a parsed config file as its config types, with a trailing newline
+    pub fn write_config(self, value: serde_json::Value) -> Result<String, Box<dyn Error>> {
+        // Keep single configs single
+        let mut text = if value.get("profiles").is_some() {
+            self.write(&serde_json::from_value::<ProfileSet>(value)?)?
+        } else {
+            self.write(&serde_json::from_value::<Config>(value)?)?
+        };
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+
+        Ok(text)
+    }
+
+    pub fn write<T: Serialize>(self, value: &T) -> Result<String, String> {
+        match self {
+            Format::Json => {
+                // Match the 4-space indent of the files in joy-config/
+                let mut out = Vec::new();
+                let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+                let mut ser = serde_json::Serializer::with_formatter(&mut out, formatter);
+                value.serialize(&mut ser).map_err(|e| e.to_string())?;
+                String::from_utf8(out).map_err(|e| e.to_string())
+            }
+
+            // NOTE: the toml serializer cannot write enum variants with data directly
+            //   Going through JSON's data model turns them into tables
+            //   Go through JSON text rather than serde_json::to_value(), which widens
+            //   f32s (0.3 becomes 0.30000001192092896)
+            Format::Toml => {
+                let value: serde_json::Value = serde_json::to_string(value)
+                    .and_then(|json| serde_json::from_str(&json))
+                    .map_err(|e| e.to_string())?;
+                let value = toml::Value::try_from(value)
+                    .map_err(|e| format!("Cannot write TOML: {}", e))?;
+                toml::to_string_pretty(&value).map_err(|e| e.to_string())
+            }
+
+            Format::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+
+            Format::Ron => {
+                ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// The top-level fields of a RON file that decide how it is read, anything else is skipped
+/// Stays at the default if the file does not parse as a struct
+#[derive(Default, Deserialize)]
+struct RonFields {
+    #[serde(default)]
+    extends: Present,
+    #[serde(default)]
+    profiles: Present,
+}
+
+/// True if the field is there, whatever its value
+#[derive(Default)]
+struct Present(bool);
+
+impl<'de> Deserialize<'de> for Present {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IgnoredAny::deserialize(deserializer)?;
+        Ok(Present(true))
+    }
+}
+
+/// Reads a config file of any supported format
+pub fn read_value(path: &str) -> Result<serde_json::Value, Box<dyn Error>> {
+    let format = Format::from_path(Path::new(path))?;
+    let text = fs::read_to_string(path)?;
+    let value = format.parse(&text)
+        .map_err(|e| format!("Could not read '{}': {}", path, e))?;
+
+    Ok(value)
+}
+
+/// Writes a parsed config file in the format of `path`
+pub fn write_value(path: &str, value: serde_json::Value) -> Result<(), Box<dyn Error>> {
+    let format = Format::from_path(Path::new(path))?;
+    fs::write(path, format.write_config(value)?)?;
+
+    Ok(())
+}
+
+/// Writes a document as is, without reading it as a config
+/// Used for files that extend others and may not be complete configs
+pub fn write_document(path: &str, value: &serde_json::Value) -> Result<(), Box<dyn Error>> {
+    let format = Format::from_path(Path::new(path))?;
+    if format == Format::Ron {
+        return Err("'extends' is only supported in JSON, TOML and YAML files".into());
+    }
+
+    let mut text = format.write(value)?;
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    fs::write(path, text)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ron_single_config_mentioning_profiles() {
+        let text = "// based on profiles.json
+(version: 2, mode: Normal, buttons: {})";
+        let value = Format::Ron.parse(text).unwrap();
+        assert!(value.get("profiles").is_none());
+        assert_eq!(value["version"], 2);
+    }
+
+    #[test]
+    fn ron_profile_named_extends() {
+        let text = "(
+    version: 2,
+    profiles: [(name: \"extends\", config: (version: 2, mode: Normal, buttons: {}))],
+)";
+        let value = Format::Ron.parse(text).unwrap();
+        assert_eq!(value["profiles"][0]["name"], "extends");
+    }
+
+    #[test]
+    fn ron_extends_is_rejected() {
+        let text = "(extends: \"base.ron\", version: 2, mode: Normal, buttons: {})";
+        assert!(Format::Ron.parse(text).unwrap_err().contains("extends"));
+    }
+}
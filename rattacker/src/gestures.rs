@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::config::{Direction, Gesture, GestureConfig, Rotation};
+use super::dispatch::State;
+
+/// Degrees either side of a direction that still count as it
+const DIRECTION_TOLERANCE: f32 = 22.5;
+
+/// Recognizes stick gestures in a stream of States
+/// Each gesture is tracked on its own, so one movement can complete several
+pub struct Recognizer {
+    trackers: Vec<Tracker>,
+}
+
+impl Recognizer {
+    pub fn new(gestures: &[GestureConfig]) -> Self {
+        Recognizer {
+            trackers: gestures.iter().map(Tracker::new).collect(),
+        }
+    }
+
+    /// Indices of the gestures completed by s, which was read at now
+    pub fn next(&mut self, now: Instant, s: &State) -> Vec<usize> {
+        let (x, y) = (s.x_axis(), s.y_axis());
+        self.trackers.iter_mut()
+            .enumerate()
+            .filter_map(|(i, tracker)| if tracker.next(now, x, y) { Some(i) } else { None })
+            .collect()
+    }
+
+    /// Forgets any gesture in progress
+    pub fn reset(&mut self) {
+        for tracker in self.trackers.iter_mut() {
+            tracker.reset();
+        }
+    }
+}
+
+struct Tracker {
+    gesture: Gesture,
+    window: Duration,
+    threshold: f32,
+    center: f32,
+
+    /// Flick: when the stick left the center and the furthest (radius, angle) since
+    excursion: Option<(Instant, f32, f32)>,
+    /// Turns: (time, angle) of every sample past the threshold within the window
+    ///   The angle is unwrapped, so it keeps growing past 360 clockwise
+    stroke: VecDeque<(Instant, f32)>,
+    /// The (x, y) sample before, the same State is stepped again until a new report arrives
+    last: Option<(f32, f32)>,
+}
+
+impl Tracker {
+    fn new(cfg: &GestureConfig) -> Self {
+        Tracker {
+            gesture: cfg.gesture,
+            window: Duration::from_secs_f32(cfg.window),
+            threshold: cfg.threshold,
+            center: cfg.center,
+
+            excursion: None,
+            stroke: VecDeque::new(),
+            last: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.excursion = None;
+        self.stroke.clear();
+        self.last = None;
+    }
+
+    fn next(&mut self, now: Instant, x: f32, y: f32) -> bool {
+        // A repeated sample can't complete anything the one before didn't
+        if self.last == Some((x, y)) {
+            return false;
+        }
+        self.last = Some((x, y));
+
+        let radius = x.hypot(y);
+        let angle = angle(x, y);
+        match self.gesture {
+            Gesture::Flick(direction) => self.flick(now, radius, angle, direction),
+            Gesture::QuarterCircle { from, rotation } => {
+                self.turn(now, radius, angle, rotation, 90.0, Some(from))
+            }
+            Gesture::Circle(rotation) => self.turn(now, radius, angle, rotation, 360.0, None),
+        }
+    }
+
+    fn flick(&mut self, now: Instant, radius: f32, angle: f32, direction: Direction) -> bool {
+        if radius > self.center {
+            match self.excursion {
+                Some((_, ref mut peak, ref mut peak_angle)) => {
+                    if radius > *peak {
+                        *peak = radius;
+                        *peak_angle = angle;
+                    }
+                }
+                None => self.excursion = Some((now, radius, angle)),
+            }
+            return false;
+        }
+
+        match self.excursion.take() {
+            Some((since, peak, peak_angle)) => {
+                now.duration_since(since) <= self.window
+                    && peak >= self.threshold
+                    && distance(peak_angle, direction.angle()) <= DIRECTION_TOLERANCE
+            }
+            None => false,
+        }
+    }
+
+    fn turn(
+        &mut self,
+        now: Instant,
+        radius: f32,
+        angle: f32,
+        rotation: Rotation,
+        degrees: f32,
+        from: Option<Direction>,
+    ) -> bool {
+        // Dropping below the threshold ends the stroke
+        if radius < self.threshold {
+            self.stroke.clear();
+            return false;
+        }
+
+        let unwrapped = match self.stroke.back() {
+            Some(&(_, last)) => last + difference(last, angle),
+            None => angle,
+        };
+        self.stroke.push_back((now, unwrapped));
+        while let Some(&(t, _)) = self.stroke.front() {
+            if now.duration_since(t) <= self.window {
+                break;
+            }
+            self.stroke.pop_front();
+        }
+
+        let sign = match rotation {
+            Rotation::Clockwise => 1.0,
+            Rotation::CounterClockwise => -1.0,
+        };
+        let done = self.stroke.iter().any(|&(_, start)| {
+            let starts_right = match from {
+                Some(from) => distance(start, from.angle()) <= DIRECTION_TOLERANCE,
+                None => true,
+            };
+            starts_right && sign * (unwrapped - start) >= degrees
+        });
+        if done {
+            // Another turn has to start over
+            self.stroke.clear();
+        }
+
+        done
+    }
+}
+
+/// Degrees clockwise from Up, in range [0, 360)
+fn angle(x: f32, y: f32) -> f32 {
+    x.atan2(y).to_degrees().rem_euclid(360.0)
+}
+
+/// Signed degrees to turn from a to b the short way, clockwise positive
+fn difference(a: f32, b: f32) -> f32 {
+    (b - a + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Degrees between a and b the short way, in range [0, 180]
+fn distance(a: f32, b: f32) -> f32 {
+    difference(a, b).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::GestureConfig;
+    use crate::raw_input::Packet;
+
+    fn gesture(gesture: serde_json::Value) -> GestureConfig {
+        serde_json::from_value(json!({
+            "gesture": gesture,
+            "action": "E",
+            "window": 0.3,
+            "threshold": 0.6,
+        })).unwrap()
+    }
+
+    /// The stick deflected by radius at degrees clockwise from Up
+    fn state(degrees: f32, radius: f32) -> State {
+        let (x, y) = (degrees.to_radians().sin() * radius, degrees.to_radians().cos() * radius);
+        let axis = |f: f32| (128.0 + f * 127.5).round().clamp(0.0, 255.0) as u8;
+        State::from_packet([0x80, 0x80], Packet {
+            buttons: [false; 11],
+            x_axis: axis(x),
+            y_axis: axis(-y),
+            z_axis: 0x80,
+        })
+    }
+
+    /// Feeds (milliseconds, degrees, radius) samples and collects every recognized gesture
+    fn recognize(gestures: &[GestureConfig], samples: &[(u64, f32, f32)]) -> Vec<usize> {
+        let start = Instant::now();
+        let mut recognizer = Recognizer::new(gestures);
+        samples.iter()
+            .flat_map(|&(ms, degrees, radius)| {
+                recognizer.next(start + Duration::from_millis(ms), &state(degrees, radius))
+            })
+            .collect()
+    }
+
+    /// Out to full deflection at degrees and back to the center over ms
+    fn flick(degrees: f32, ms: u64) -> Vec<(u64, f32, f32)> {
+        (0..=10)
+            .map(|i| {
+                let radius = 1.0 - (i as f32 - 5.0).abs() / 5.0;
+                (i * ms / 10, degrees, radius)
+            })
+            .collect()
+    }
+
+    /// Along the rim from one angle to another over ms, crossing 0/360 if to is past 360
+    fn arc(from: f32, to: f32, ms: u64) -> Vec<(u64, f32, f32)> {
+        (0..=20)
+            .map(|i| (i * ms / 20, from + (to - from) * i as f32 / 20.0, 0.9))
+            .collect()
+    }
+
+    #[test]
+    fn flick_within_window() {
+        let gestures = [gesture(json!({ "Flick": "Right" }))];
+        assert_eq!(recognize(&gestures, &flick(90.0, 200)), vec![0]);
+    }
+
+    #[test]
+    fn flick_outside_window() {
+        let gestures = [gesture(json!({ "Flick": "Right" }))];
+        assert!(recognize(&gestures, &flick(90.0, 500)).is_empty());
+    }
+
+    #[test]
+    fn flick_in_wrong_direction() {
+        let gestures = [gesture(json!({ "Flick": "Right" }))];
+        assert_eq!(recognize(&gestures, &flick(110.0, 200)), vec![0]);
+        assert!(recognize(&gestures, &flick(120.0, 200)).is_empty());
+        assert!(recognize(&gestures, &flick(60.0, 200)).is_empty());
+    }
+
+    #[test]
+    fn quarter_circle_from() {
+        let gestures = [gesture(json!({ "QuarterCircle": { "from": "Down", "rotation": "CounterClockwise" } }))];
+        assert_eq!(recognize(&gestures, &arc(180.0, 80.0, 200)), vec![0]);
+        // Same turn, started from Left
+        assert!(recognize(&gestures, &arc(270.0, 170.0, 200)).is_empty());
+        // From Down, but the other way
+        assert!(recognize(&gestures, &arc(180.0, 280.0, 200)).is_empty());
+    }
+
+    #[test]
+    fn circle_rotation() {
+        let mut gestures = vec![
+            gesture(json!({ "Circle": "Clockwise" })),
+            gesture(json!({ "Circle": "CounterClockwise" })),
+        ];
+        for g in gestures.iter_mut() {
+            g.window = 1.0;
+        }
+
+        assert_eq!(recognize(&gestures, &arc(10.0, 380.0, 800)), vec![0]);
+        assert_eq!(recognize(&gestures, &arc(380.0, 10.0, 800)), vec![1]);
+        // Too slow for the window
+        assert!(recognize(&gestures, &arc(10.0, 380.0, 2000)).is_empty());
+    }
+
+    #[test]
+    fn turn_across_up() {
+        let gestures = [gesture(json!({ "QuarterCircle": { "from": "UpLeft", "rotation": "Clockwise" } }))];
+        assert_eq!(recognize(&gestures, &arc(315.0, 415.0, 200)), vec![0]);
+    }
+
+    #[test]
+    fn difference_wraps_around() {
+        assert_eq!(difference(350.0, 10.0), 20.0);
+        assert_eq!(difference(10.0, 350.0), -20.0);
+        assert_eq!(difference(0.0, 180.0).abs(), 180.0);
+        assert_eq!(distance(355.0, 5.0), 10.0);
+    }
+
+    #[test]
+    fn repeated_state_is_recorded_once() {
+        let gestures = [gesture(json!({ "Circle": "Clockwise" }))];
+        let mut recognizer = Recognizer::new(&gestures);
+        let (start, s) = (Instant::now(), state(90.0, 0.9));
+        for i in 0..10_000 {
+            recognizer.next(start + Duration::from_micros(i), &s);
+        }
+        assert_eq!(recognizer.trackers[0].stroke.len(), 1);
+    }
+}
@@ -0,0 +1,256 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+use super::format;
+use super::migrate;
+
+/// Key naming the file(s) an object is based on
+/// At the top of a file it inherits a whole config, anywhere else it pulls in a fragment
+const EXTENDS: &str = "extends";
+
+/// A config file with every `extends` replaced by the files it names
+pub struct Resolved {
+    pub value: Value,
+    /// Every file read, starting with the one resolved
+    pub files: Vec<PathBuf>,
+    /// Migration warnings of each file that uses an old config version
+    pub warnings: Vec<(String, Vec<String>)>,
+}
+
+/// Reads a config file and everything it extends
+/// Each file is migrated on its own before being merged
+pub fn resolve(path: &str) -> Result<Resolved, Box<dyn Error>> {
+    let mut resolver = Resolver {
+        stack: Vec::new(),
+        files: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let value = resolver.load(Path::new(path), false)?;
+
+    Ok(Resolved {
+        value,
+        files: resolver.files,
+        warnings: resolver.warnings,
+    })
+}
+
+/// True if the document extends other files
+/// Such documents may be partial configs
+pub fn uses_extends(value: &Value) -> bool {
+    match value {
+        Value::Object(obj) => obj.contains_key(EXTENDS) || obj.values().any(uses_extends),
+        Value::Array(array) => array.iter().any(uses_extends),
+        _ => false,
+    }
+}
+
+struct Resolver {
+    /// Files currently being read as (canonical path, path as written)
+    stack: Vec<(PathBuf, String)>,
+    files: Vec<PathBuf>,
+    warnings: Vec<(String, Vec<String>)>,
+}
+
+impl Resolver {
+    fn load(&mut self, path: &Path, nested: bool) -> Result<Value, Box<dyn Error>> {
+        let name = path.display().to_string();
+        let canonical = fs::canonicalize(path).map_err(|e| match self.stack.last() {
+            Some((_, parent)) => format!("Could not read '{}' (extended by '{}'): {}", name, parent, e),
+            None => format!("Could not read '{}': {}", name, e),
+        })?;
+
+        if let Some(start) = self.stack.iter().position(|(p, _)| *p == canonical) {
+            let chain: Vec<&str> = self.stack[start..]
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .chain(std::iter::once(name.as_str()))
+                .collect();
+            return Err(format!("Config files extend each other in a cycle: {}", chain.join(" -> ")).into());
+        }
+
+        let mut value = format::read_value(&name)?;
+        let warnings = migrate::migrate(&mut value)
+            .map_err(|e| format!("Could not read '{}': {}", name, e))?;
+
+        // Files can be extended more than once, only report them once
+        if !self.files.contains(&canonical) {
+            self.files.push(canonical.clone());
+            if !warnings.is_empty() {
+                self.warnings.push((name.clone(), warnings));
+            }
+        }
+
+        // The version belongs to the file, not to the object it is merged into
+        if nested {
+            if let Value::Object(obj) = &mut value {
+                obj.remove("version");
+            }
+        }
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        self.stack.push((canonical, name));
+        let expanded = self.expand(&mut value, &dir);
+        self.stack.pop();
+        expanded?;
+
+        Ok(value)
+    }
+
+    /// Replaces every object's `extends` with the files it names, depth first
+    fn expand(&mut self, value: &mut Value, dir: &Path) -> Result<(), Box<dyn Error>> {
+        match value {
+            Value::Object(obj) => {
+                let extends = obj.remove(EXTENDS);
+                for child in obj.values_mut() {
+                    self.expand(child, dir)?;
+                }
+
+                let extends = match extends {
+                    Some(extends) => extends,
+                    None => return Ok(()),
+                };
+
+                // Later files take precedence over earlier ones, the object over all of them
+                let mut base = Value::Object(Map::new());
+                for path in paths(&extends)? {
+                    let included = self.load(&dir.join(path), true)?;
+                    merge(&mut base, included, "");
+                }
+                merge(&mut base, Value::Object(std::mem::take(obj)), "");
+                *value = base;
+            }
+
+            Value::Array(array) => {
+                for element in array.iter_mut() {
+                    self.expand(element, dir)?;
+                }
+            }
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+fn paths(extends: &Value) -> Result<Vec<&str>, String> {
+    let error = || format!("'{}' must be a path or a list of paths", EXTENDS);
+    match extends {
+        Value::String(path) => Ok(vec![path.as_str()]),
+        Value::Array(array) => array.iter()
+            .map(|path| path.as_str().ok_or_else(error))
+            .collect(),
+        _ => Err(error()),
+    }
+}
+
+/// Lays `over` on top of `base` like a JSON merge patch (RFC 7386)
+/// Objects merge key by key and `null` removes a key, anything else is replaced
+/// A different enum variant replaces the old one, the same variant merges into it
+/// `path` leads from where the merge started to `base`, e.g. `joystick.Directions`
+fn merge(base: &mut Value, over: Value, path: &str) {
+    match over {
+        Value::Object(over) => {
+            let keyed = is_keyed_map(path);
+            let switches_variant = match (variant(&over), base.as_object()) {
+                (Some(v), Some(base)) => !keyed && variant(base).is_some() && !base.contains_key(v),
+                _ => false,
+            };
+            if !base.is_object() || switches_variant {
+                *base = Value::Object(Map::new());
+            }
+
+            if let Value::Object(base) = base {
+                for (key, value) in over {
+                    if value.is_null() {
+                        base.remove(&key);
+                    } else {
+                        let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                        merge(base.entry(key).or_insert(Value::Null), value, &path);
+                    }
+                }
+            }
+        }
+
+        over => *base = over,
+    }
+}
+
+/// Paths of maps whose keys are capitalized like enum variants, never enums themselves
+/// A merge can start below the top of a config (wherever `extends` is), so these are matched as path endings
+const KEYED_MAPS: [&str; 2] = ["key_policies", "Directions.keys"];
+
+fn is_keyed_map(path: &str) -> bool {
+    KEYED_MAPS.iter().any(|keyed| {
+        path == *keyed || (path.ends_with(keyed) && path[..path.len() - keyed.len()].ends_with('.'))
+    })
+}
+
+/// Enums are written as an object with one key, the capitalized variant name
+///   e.g. `{ "Mouse": {...} }`
+/// Struct fields are lowercase
+fn variant(obj: &Map<String, Value>) -> Option<&str> {
+    if obj.len() != 1 {
+        return None;
+    }
+
+    obj.keys()
+        .next()
+        .map(|key| key.as_str())
+        .filter(|key| key.starts_with(|c: char| c.is_ascii_uppercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn merged(base: Value, over: Value) -> Value {
+        let mut base = base;
+        merge(&mut base, over, "");
+        base
+    }
+
+    #[test]
+    fn other_variant_replaces() {
+        let base = json!({ "joystick": { "Keys": { "x_axis": 1 } } });
+        let over = json!({ "joystick": { "Mouse": { "y_axis": 2 } } });
+        assert_eq!(merged(base, over), json!({ "joystick": { "Mouse": { "y_axis": 2 } } }));
+    }
+
+    #[test]
+    fn same_variant_merges() {
+        let base = json!({ "joystick": { "Keys": { "x_axis": 1 } } });
+        let over = json!({ "joystick": { "Keys": { "y_axis": 2 } } });
+        assert_eq!(merged(base, over), json!({ "joystick": { "Keys": { "x_axis": 1, "y_axis": 2 } } }));
+    }
+
+    #[test]
+    fn keyed_maps_merge() {
+        let base = json!({ "key_policies": { "W": "Retrigger" } });
+        let over = json!({ "key_policies": { "S": "Retrigger" } });
+        assert_eq!(merged(base, over), json!({ "key_policies": { "W": "Retrigger", "S": "Retrigger" } }));
+
+        let base = json!({ "Directions": { "keys": { "Up": ["W"], "Down": ["S"] } } });
+        let over = json!({ "Directions": { "keys": { "UpRight": ["W", "D"] } } });
+        assert_eq!(
+            merged(base, over),
+            json!({ "Directions": { "keys": { "Up": ["W"], "Down": ["S"], "UpRight": ["W", "D"] } } })
+        );
+    }
+
+    #[test]
+    fn other_keys_fields_are_not_keyed_maps() {
+        let base = json!({ "Keys": { "keys": { "Up": ["W"] } } });
+        let over = json!({ "Keys": { "keys": { "Down": ["S"] } } });
+        assert_eq!(merged(base, over), json!({ "Keys": { "keys": { "Down": ["S"] } } }));
+
+        let base = json!({ "joystick": { "Directions": { "keys": { "Up": ["W"] } } } });
+        let over = json!({ "joystick": { "Directions": { "keys": { "Down": ["S"] } } } });
+        assert_eq!(merged(base, over), json!({ "joystick": { "Directions": { "keys": { "Up": ["W"], "Down": ["S"] } } } }));
+    }
+}
@@ -0,0 +1,42 @@
+//! Translates Logitech Attack3 joystick input into keyboard and mouse input
+//!
+//! Input flows through these stages, each usable on its own:
+//! 1. [`raw_input::Packet::parse`] decodes a 5 byte HID report
+//! 2. [`dispatch::State::from_packet`] normalizes it, axes in `[-1.0, 1.0]`
+//! 3. [`events::Events`] turns successive States into typed events
+//! 4. [`dispatch::Manager`] maps events to actions per a [`config::Config`],
+//!    sent through a [`dispatch::Dispatcher`]
+//!
+//! [`gestures::Recognizer`] picks stick gestures out of the same States.
+//! [`device::Joystick`] does 1 and 2 for an open device.
+//! [`record::RecordDispatch`] collects actions instead of sending them, see `examples/`.
+//!
+//! Configs are read with [`includes::resolve`] (or [`format::read_value`] for a single file),
+//! then [`config::ProfileSet::from_value`] and the checks in [`validate`].
+
+extern crate hidapi;
+#[cfg(target_os = "windows")]
+extern crate bindings;
+
+pub mod raw_input;
+pub mod config;
+pub mod profiles;
+pub mod reload;
+pub mod validate;
+pub mod format;
+pub mod includes;
+pub mod migrate;
+pub mod schema;
+pub mod presets;
+pub mod device;
+pub mod events;
+pub mod gestures;
+pub mod record;
+
+mod actions;
+
+#[macro_use]
+pub mod dispatch;
+
+#[cfg(target_os = "windows")]
+pub mod win_input;
@@ -1,88 +1,378 @@
-extern crate hidapi;
-#[cfg(target_os = "windows")]
-extern crate bindings;
-
-use std::fs::File;
-use std::io::BufReader;
-use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::error::Error;
+use std::process;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use hidapi::{HidApi};
-use serde_json;
 
-mod raw_input;
-mod config;
+use rattacker::{device, format, includes, migrate, schema, validate};
+use rattacker::dispatch::*;
+use rattacker::config::*;
+use rattacker::profiles::Switcher;
+use rattacker::reload::ConfigWatcher;
+use rattacker::includes::Resolved;
+use rattacker::presets::{Preset, PRESETS};
+use rattacker::device::Joystick;
+use rattacker::record::LogDispatch;
+
+mod cli;
+mod monitor;
+mod stop;
+
+use cli::{Command, DeviceOpt, Overrides, RunOpt, SourceOpt};
 
-#[macro_use]
-mod dispatch;
+fn main() {
+    if let Err(e) = run_command(Command::parse()) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
 
-#[cfg(target_os = "windows")]
-mod win_input;
+fn run_command(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Run(opt) => run(opt),
 
-use raw_input::*;
-use dispatch::*;
-use config::*;
+        Command::ListDevices => {
+            device::list_devices(&HidApi::new()?);
+            Ok(())
+        }
 
-const PACKET_LENGTH: usize = 5;
-const VID: u16 = 0x046d;
-const PID: u16 = 0xc214;
+        Command::Monitor { device, config, preset, overrides } => {
+            let loaded = match (config, preset) {
+                (None, None) => None,
+                (config, preset) => {
+                    let source = Source::new(SourceOpt { config, preset })?;
+                    let (profiles, switcher, _) = load_profiles(&source, &overrides)?;
+                    Some(monitor::Loaded {
+                        source: source.to_string(),
+                        profiles,
+                        switcher,
+                    })
+                }
+            };
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let cfg_path = env::args()
-        .skip(1)
-        .next()
-        .expect("No JSON config path");
+            let hidapi = HidApi::new()?;
+            let joystick = Joystick::open(&hidapi, device.device)?;
+            monitor::monitor(joystick, device.device, loaded)
+        }
 
-    println!("Attempting to JSON config from '{}'", cfg_path);
+        Command::Calibrate { device, seconds } => calibrate(device, Duration::from_secs(seconds)),
 
-    let f = File::open(cfg_path)?;
-    let cfg: Config = serde_json::from_reader(BufReader::new(f))?;
+        Command::Validate { source, overrides } => {
+            let source = Source::new(source)?;
+            load_profiles(&source, &overrides)?;
+            println!("{} is valid", source);
+            Ok(())
+        }
 
-    println!("Read JSON config");
+        Command::Convert { input, output } => {
+            let resolved = read_config(&input)?;
+            format::write_value(&output, resolved.value)?;
+            println!("Converted '{}' to '{}'", input, output);
+            Ok(())
+        }
 
+        Command::Migrate { config } => {
+            let mut value = format::read_value(&config)?;
+            let from = migrate::version(&value)?;
+            if from == CONFIG_VERSION {
+                println!("'{}' is already at version {}", config, CONFIG_VERSION);
+                return Ok(());
+            }
 
-    let mut manager = Manager::new(cfg);
-    // let mut manager = Manager::dbg();
+            for change in migrate::migrate(&mut value)? {
+                println!("  {}", change);
+            }
+            // Files that extend others are only part of a config, keep them that way
+            if includes::uses_extends(&value) {
+                format::write_document(&config, &value)?;
+            } else {
+                format::write_value(&config, value)?;
+            }
+            println!("Migrated '{}' from version {} to {}", config, from, CONFIG_VERSION);
+            Ok(())
+        }
+
+        Command::Resolve { config } => {
+            let resolved = read_config(&config)?;
+            let format = format::Format::from_path(Path::new(&config))?;
+            print!("{}", format.write_config(resolved.value)?);
+            Ok(())
+        }
+
+        Command::Schema { output } => {
+            let text = format::Format::Json.write(&schema::config_schema())?;
+            match output {
+                Some(output) => {
+                    fs::write(&output, text + "\n")?;
+                    println!("Wrote the config schema to '{}'", output);
+                }
+                None => println!("{}", text),
+            }
+            Ok(())
+        }
+
+        Command::Preset { name: None, .. } => {
+            for preset in PRESETS.iter() {
+                println!("{:16}{}", preset.name, preset.description);
+            }
+            Ok(())
+        }
+
+        Command::Preset { name: Some(name), output } => {
+            let output = output.ok_or("No output config path")?;
+            let preset = Preset::find(&name)?;
+            format::write_value(&output, preset.value()?)?;
+            println!("Wrote preset '{}' to '{}'", preset.name, output);
+            Ok(())
+        }
+    }
+}
+
+fn run(opt: RunOpt) -> Result<(), Box<dyn Error>> {
+    let source = Source::new(opt.source)?;
+    println!("Attempting to read config from {}", source);
+
+    let (mut profiles, mut switcher, files) = load_profiles(&source, &opt.overrides)?;
+    let mut watcher = ConfigWatcher::new(files);
+
+    println!("Read config with {} profile(s)", profiles.profiles.len());
+
+    let log = if opt.dry_run {
+        println!("Dry run, printing input instead of sending it");
+        Some(Rc::new(LogDispatch::new()))
+    } else {
+        None
+    };
+
+    let mut current = 0;
+    println!("Using profile '{}'", profiles.profiles[current].name);
+    let mut manager = new_manager(&profiles.profiles[current].config, log.as_ref(), opt.verbose);
     let hidapi = HidApi::new()?;
 
-    println!("Attempting to open the Attack3...");
-    let attack3 = hidapi.open(VID, PID)?;
-    attack3.set_blocking_mode(false)?;
-    println!("Opened the Attack3");
+    println!("Attempting to open {}...", opt.device.device);
+    let mut joystick = Joystick::open(&hidapi, opt.device.device)?;
+    println!("Opened the joystick");
 
-    let mut zeroed = false;
-    let mut buffer = [0u8; 1024];
-    let mut zero = [0, 0];
     let mut s: Option<State> = None;
+    let mut latched = latched_toggles(&manager);
+    let mut active_layer: Option<String> = None;
+    stop::install();
     println!("Waiting to zero...");
     loop {
+        if stop::requested() {
+            println!("Stopping, releasing held keys...");
+            manager.release_all();
+            return Ok(());
+        }
+
+        if watcher.poll() {
+            match load_profiles(&source, &opt.overrides) {
+                Ok((new_profiles, new_switcher, files)) => {
+                    // Stay on the same profile if it still exists
+                    let name = &profiles.profiles[current].name;
+                    current = new_profiles.profiles
+                        .iter()
+                        .position(|p| p.name == *name)
+                        .unwrap_or(0);
+                    profiles = new_profiles;
+                    switcher = new_switcher;
+                    // The files extended may have changed too
+                    watcher = ConfigWatcher::new(files);
+
+                    manager.release_all();
+                    manager = new_manager(&profiles.profiles[current].config, log.as_ref(), opt.verbose);
+                    latched = latched_toggles(&manager);
+                    active_layer = None;
+                    println!("Reloaded config, using profile '{}'", profiles.profiles[current].name);
+                }
+
+                Err(e) => {
+                    println!("Rejected config change, keeping the old config: {}", e);
+                }
+            }
+        }
 
         if let Some(ref s) = s {
             manager.step(s.clone());
         }
 
-        let read_len = attack3.read(&mut buffer);
-        if let Ok(read_len) = read_len {
-            if read_len == 0 {
-                continue;
+        for (t, was_latched) in manager.toggles().iter().zip(latched.iter_mut()) {
+            if t.is_latched() != *was_latched {
+                *was_latched = t.is_latched();
+                println!("{} toggle ({:?}): {}",
+                    Button(t.button),
+                    t.key,
+                    if *was_latched { "held" } else { "released" });
             }
-            let packet_count = read_len / PACKET_LENGTH;
-            // println!("Received {} packets", packet_count);
-            for i in 0..packet_count {
-                let start = i * PACKET_LENGTH;
-                let p = Packet::parse(&buffer[start..start + PACKET_LENGTH])?;
-
-                if !zeroed {
-                    zero[0] = p.x_axis;
-                    zero[1] = p.y_axis;
-                    zeroed = true;
-                    println!("Zeroed!");
-                    continue;
-                }
-                s = Some(State::from_packet(zero, p));
-                // println!("{}", p);
-                // println!("{}", s);
+        }
+
+        if manager.active_layer() != active_layer.as_deref() {
+            active_layer = manager.active_layer().map(|l| l.to_string());
+            println!("Active layer: {}", active_layer.as_deref().unwrap_or("base"));
+        }
+
+        let was_zeroed = joystick.is_zeroed();
+        let read = match joystick.read() {
+            Ok(read) => read,
+            Err(e) => {
+                println!("Lost the joystick, releasing held keys...");
+                manager.release_all();
+                return Err(e);
             }
+        };
+        if !was_zeroed && joystick.is_zeroed() {
+            println!("Zeroed!");
+        }
+
+        for report in read {
+            let state = report.state;
+            if let Some(next) = switcher.check(&state, current) {
+                // Device stays open, only the bindings are rebuilt
+                manager.release_all();
+                current = next;
+                manager = new_manager(&profiles.profiles[current].config, log.as_ref(), opt.verbose);
+                latched = latched_toggles(&manager);
+                active_layer = None;
+                println!("Switched to profile '{}'", profiles.profiles[current].name);
+            }
+
+            s = Some(state);
+        }
+    }
+}
+
+/// --dry-run prints the input the config would send instead of sending it
+/// --verbose prints every event the joystick's input turns into
+fn new_manager(cfg: &Config, log: Option<&Rc<LogDispatch>>, verbose: bool) -> Manager {
+    let mut manager = match log {
+        Some(log) => Manager::with_dispatcher(cfg.clone(), log.clone()),
+        None => Manager::new(cfg.clone()),
+    };
+    if verbose {
+        manager.subscribe(|event| println!("{}", event));
+    }
+
+    manager
+}
+
+/// Samples the stick at rest and at full deflection
+fn calibrate(opt: DeviceOpt, duration: Duration) -> Result<(), Box<dyn Error>> {
+    let hidapi = HidApi::new()?;
+    let mut joystick = Joystick::open(&hidapi, opt.device)?;
+    println!("Opened {}", opt.device);
+
+    // The stick is zeroed on the first packet, so it has to be centered first
+    println!("Let go of the stick and leave it centered...");
+    let rest = sample(&mut joystick, duration)?;
+    println!("Move the stick slowly around the edge of its range...");
+    let range = sample(&mut joystick, duration)?;
+
+    let noise = |axis: fn(&State) -> f32| rest.iter()
+        .map(|s| axis(s).abs())
+        .fold(0.0f32, f32::max);
+    let extent = |axis: fn(&State) -> f32| range.iter()
+        .map(axis)
+        .fold((0.0f32, 0.0f32), |(min, max), v| (min.min(v), max.max(v)));
+
+    let (x_noise, y_noise) = (noise(State::x_axis), noise(State::y_axis));
+    let (x_min, x_max) = extent(State::x_axis);
+    let (y_min, y_max) = extent(State::y_axis);
+    println!("Resting noise: x {:.2}, y {:.2}", x_noise, y_noise);
+    println!("Range: x {:+.2} to {:+.2}, y {:+.2} to {:+.2}", x_min, x_max, y_min, y_max);
+
+    // Leave some margin over the noise so a resting stick never leaves the deadzone
+    let suggest = |noise: f32| ((noise + 0.05) * 100.0).ceil() / 100.0;
+    println!("Suggested deadzones: --x-deadzone {:.2} --y-deadzone {:.2}",
+        suggest(x_noise), suggest(y_noise));
+
+    Ok(())
+}
+
+fn sample(joystick: &mut Joystick, duration: Duration) -> Result<Vec<State>, Box<dyn Error>> {
+    let start = Instant::now();
+    let mut states = Vec::new();
+    while start.elapsed() < duration {
+        states.extend(joystick.read()?.into_iter().map(|report| report.state));
+    }
+
+    Ok(states)
+}
+
+/// Reads a config file and everything it extends, upgraded to the current schema
+fn read_config(path: &str) -> Result<Resolved, Box<dyn Error>> {
+    let resolved = includes::resolve(path)?;
+    for (file, warnings) in resolved.warnings.iter() {
+        for w in warnings.iter() {
+            println!("Warning: '{}' uses an old config version, {}", file, w);
+        }
+        println!("Run 'rattacker migrate {}' to update it", file);
+    }
+
+    Ok(resolved)
+}
+
+/// Where the config comes from
+enum Source {
+    File(String),
+    Preset(&'static Preset),
+}
+
+impl Source {
+    fn new(opt: SourceOpt) -> Result<Source, String> {
+        match (opt.config, opt.preset) {
+            (_, Some(name)) => Preset::find(&name).map(Source::Preset),
+            (Some(path), None) => Ok(Source::File(path)),
+            (None, None) => Err("No config path or preset".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::File(path) => write!(f, "'{}'", path),
+            Source::Preset(preset) => write!(f, "preset '{}'", preset.name),
         }
     }
 }
+
+/// Also returns every file read, for the watcher
+/// Presets are compiled in, so there is nothing to watch
+fn load_profiles(
+    source: &Source,
+    overrides: &Overrides,
+) -> Result<(ProfileSet, Switcher, Vec<PathBuf>), Box<dyn Error>> {
+    let (mut value, files) = match source {
+        Source::File(path) => {
+            let Resolved { value, files, .. } = read_config(path)?;
+            (value, files)
+        }
+
+        Source::Preset(preset) => (preset.value()?, Vec::new()),
+    };
+    overrides.apply_value(&mut value)?;
+    let single = value.get("profiles").is_none();
+    let mut profiles = ProfileSet::from_value(value)?;
+    overrides.apply(&mut profiles);
+
+    // Report paths as they appear in the file
+    if single {
+        validate::validate_config(&profiles.profiles[0].config)?;
+    } else {
+        validate::validate_profiles(&profiles)?;
+    }
+    let switcher = Switcher::new(&profiles)?;
+
+    Ok((profiles, switcher, files))
+}
+
+fn latched_toggles(manager: &Manager) -> Vec<bool> {
+    manager.toggles()
+        .iter()
+        .map(|t| t.is_latched())
+        .collect()
+}
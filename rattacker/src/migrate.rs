@@ -0,0 +1,164 @@
+use serde_json::{Map, Value};
+
+use super::config::{Button, BUTTON_LEN, CONFIG_VERSION};
+
+/// Upgrades one config from version n to n + 1
+/// Warnings are pushed for anything the user should know about
+type Migration = fn(cfg: &mut Value, path: &str, warnings: &mut Vec<String>);
+
+/// MIGRATIONS[i] upgrades version i + 1 to i + 2
+const MIGRATIONS: [Migration; CONFIG_VERSION as usize - 1] = [
+    v1_named_buttons,
+];
+
+/// Upgrades a parsed config file to CONFIG_VERSION in place
+/// Documents without a `version` are version 1, the original `mode/buttons/joystick` layout
+/// Returns the warnings from every migration that ran
+pub fn migrate(doc: &mut Value) -> Result<Vec<String>, String> {
+    let version = version(doc)?;
+    let mut warnings = Vec::new();
+    let obj = doc.as_object_mut()
+        .ok_or_else(|| "Config must be an object".to_string())?;
+
+    for v in version..CONFIG_VERSION {
+        let migration = MIGRATIONS[v as usize - 1];
+        let mut step_warnings = Vec::new();
+
+        // Profile sets share one version across all of their configs
+        match obj.get_mut("profiles") {
+            Some(Value::Array(profiles)) => {
+                for (i, profile) in profiles.iter_mut().enumerate() {
+                    if let Some(cfg) = profile.get_mut("config") {
+                        migration(cfg, &format!("profiles[{}].config.", i), &mut step_warnings);
+                    }
+                }
+            }
+
+            _ => {
+                let mut cfg = Value::Object(std::mem::take(obj));
+                migration(&mut cfg, "", &mut step_warnings);
+                if let Value::Object(cfg) = cfg {
+                    *obj = cfg;
+                }
+            }
+        }
+
+        for w in step_warnings {
+            warnings.push(format!("version {} -> {}: {}", v, v + 1, w));
+        }
+    }
+
+    obj.insert("version".to_string(), Value::from(CONFIG_VERSION));
+    if let Some(Value::Array(profiles)) = obj.get_mut("profiles") {
+        for profile in profiles.iter_mut() {
+            if let Some(Value::Object(cfg)) = profile.get_mut("config") {
+                cfg.insert("version".to_string(), Value::from(CONFIG_VERSION));
+            }
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Schema version of a parsed config file
+pub fn version(doc: &Value) -> Result<u32, String> {
+    let version = match doc.get("version") {
+        None => 1,
+        Some(v) => v.as_u64()
+            .filter(|v| *v >= 1 && *v <= u32::MAX as u64)
+            .ok_or_else(|| format!("Config version must be a positive integer, not {}", v))?
+            as u32,
+    };
+
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "Config version {} is newer than this rattacker supports (up to {}), please update rattacker",
+            version, CONFIG_VERSION
+        ));
+    }
+
+    Ok(version)
+}
+
+/// Version 2 binds buttons in a map keyed by physical name instead of a positional array
+fn v1_named_buttons(cfg: &mut Value, path: &str, warnings: &mut Vec<String>) {
+    named_buttons(cfg, &format!("{}buttons", path), warnings);
+
+    if let Some(Value::Array(layers)) = cfg.get_mut("layers") {
+        for (i, layer) in layers.iter_mut().enumerate() {
+            named_buttons(layer, &format!("{}layers[{}].buttons", path, i), warnings);
+        }
+    }
+}
+
+fn named_buttons(obj: &mut Value, path: &str, warnings: &mut Vec<String>) {
+    let buttons = match obj.get_mut("buttons") {
+        Some(buttons) => buttons,
+        None => return,
+    };
+
+    let array = match buttons {
+        // Leave bad arrays alone so parsing reports them
+        Value::Array(array) if array.len() == BUTTON_LEN => array,
+        _ => return,
+    };
+
+    let mut map = Map::new();
+    for (i, b) in array.iter().enumerate() {
+        if !b.is_null() {
+            map.insert(Button(i).name().to_string(), b.clone());
+        }
+    }
+
+    *buttons = Value::Object(map);
+    warnings.push(format!("{}: positional array replaced by a map of button names", path));
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::format::Format;
+
+    #[test]
+    fn v1_buttons_round_trip() {
+        let mut doc = json!({
+            "version": 1,
+            "mode": "Normal",
+            "buttons": ["E", null, { "Toggle": "Q" }, null, null, null, null, null, null, null, "Escape"]
+        });
+        let warnings = migrate(&mut doc).unwrap();
+        assert_eq!(warnings, vec!["version 1 -> 2: buttons: positional array replaced by a map of button names"]);
+        assert_eq!(doc, json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": { "trigger": "E", "thumb_up": { "Toggle": "Q" }, "base_right_front": "Escape" }
+        }));
+
+        let cfg: Config = serde_json::from_value(doc.clone()).unwrap();
+        assert_eq!(serde_json::to_value(cfg).unwrap(), doc);
+    }
+
+    #[test]
+    fn missing_version_is_migrated() {
+        let mut doc = json!({ "mode": "Normal", "buttons": [null, null, null, null, null, null, null, null, null, null, "E"] });
+        assert_eq!(version(&doc), Ok(1));
+        assert_eq!(migrate(&mut doc).unwrap().len(), 1);
+        assert_eq!(doc, json!({ "version": 2, "mode": "Normal", "buttons": { "base_right_front": "E" } }));
+    }
+
+    #[test]
+    fn missing_version_in_ron() {
+        let doc = Format::Ron.parse("(mode: Normal, buttons: {})").unwrap();
+        assert_eq!(version(&doc), Ok(1));
+    }
+
+    #[test]
+    fn future_version_is_rejected() {
+        let mut doc = json!({ "version": CONFIG_VERSION + 1, "mode": "Normal", "buttons": {} });
+        let e = migrate(&mut doc).unwrap_err();
+        assert!(e.contains("newer than this rattacker supports"), "{}", e);
+    }
+}
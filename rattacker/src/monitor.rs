@@ -0,0 +1,304 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::{self, Stdout, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crossterm::{execute, queue};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+
+use rattacker::config::*;
+use rattacker::device::{DeviceId, Joystick, Report};
+use rattacker::dispatch::{Action, Manager, State};
+use rattacker::profiles::Switcher;
+use rattacker::record::RecordDispatch;
+
+/// Redraws per second
+/// Reports arrive far faster, the screen only shows the latest
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Actions shown, newest last
+const ACTION_LINES: usize = 10;
+
+/// Stick plot size in characters, odd so the center is a character
+/// Twice as wide as tall since characters are about twice as tall as wide
+const PLOT_WIDTH: usize = 41;
+const PLOT_HEIGHT: usize = 21;
+
+/// Width of the text left of the plot
+const INFO_WIDTH: usize = 52;
+
+/// A config to show deadzones and actions for
+pub struct Loaded {
+    /// Where it came from, e.g. `'joy-config/turbo.json'`
+    pub source: String,
+    pub profiles: ProfileSet,
+    pub switcher: Switcher,
+}
+
+/// Shows what the joystick sends and what the config would do with it
+/// Actions are recorded, never sent
+pub fn monitor(mut joystick: Joystick, device: DeviceId, loaded: Option<Loaded>) -> Result<(), Box<dyn Error>> {
+    let mut view = View {
+        device,
+        start: Instant::now(),
+        report: None,
+        actions: VecDeque::new(),
+    };
+
+    let mut current = 0;
+    let mut bound = loaded.map(|loaded| {
+        let (manager, recorder) = record(&loaded.profiles.profiles[current].config);
+        (loaded, manager, recorder)
+    });
+
+    let mut out = io::stdout();
+    let _screen = Screen::enter(&mut out)?;
+    let mut last_frame: Option<Instant> = None;
+    loop {
+        for report in joystick.read()? {
+            if let Some((loaded, manager, recorder)) = bound.as_mut() {
+                if let Some(next) = loaded.switcher.check(&report.state, current) {
+                    current = next;
+                    let (new_manager, new_recorder) = record(&loaded.profiles.profiles[current].config);
+                    *manager = new_manager;
+                    *recorder = new_recorder;
+                }
+            }
+
+            view.report = Some(report);
+        }
+
+        if let Some((_, manager, recorder)) = bound.as_mut() {
+            if let Some(ref report) = view.report {
+                manager.step(report.state.clone());
+            }
+            view.push_actions(recorder.take());
+        }
+
+        if matches!(last_frame, Some(t) if t.elapsed() < FRAME_INTERVAL) {
+            continue;
+        }
+        last_frame = Some(Instant::now());
+
+        if quit_requested()? {
+            return Ok(());
+        }
+
+        let config = bound.as_ref().map(|(loaded, manager, _)| {
+            let profile = &loaded.profiles.profiles[current];
+            ConfigView {
+                source: &loaded.source,
+                profile: &profile.name,
+                layer: manager.active_layer(),
+                deadzone: deadzone(&profile.config, manager.active_layer()),
+            }
+        });
+        draw(&mut out, &view.lines(config))?;
+    }
+}
+
+fn record(cfg: &Config) -> (Manager, Rc<RecordDispatch>) {
+    let recorder = Rc::new(RecordDispatch::new());
+    let manager = Manager::with_dispatcher(cfg.clone(), recorder.clone());
+    (manager, recorder)
+}
+
+/// Where the stick does nothing, shaped like its config measures it
+#[derive(Clone, Copy)]
+enum Deadzone {
+    /// Both axes within their own deadzones
+    Axes(f32, f32),
+    /// Within one distance of the center, see DirectionsConfig
+    Radial(f32),
+}
+
+/// Deadzone of the stick config in use
+/// The active layer's if it has one, otherwise the base config's
+fn deadzone(cfg: &Config, layer: Option<&str>) -> Option<Deadzone> {
+    let layer_stick = layer.and_then(|name| {
+        cfg.layers.iter()
+            .find(|l| l.name == name)
+            .and_then(|l| l.joystick.as_ref())
+    });
+
+    layer_stick.or(cfg.joystick.as_ref()).map(|j| match j {
+        JoystickConfig::Directions(directions) => Deadzone::Radial(directions.deadzone),
+        _ => {
+            let (x, y) = j.deadzones();
+            Deadzone::Axes(x, y)
+        }
+    })
+}
+
+/// Raw mode on an alternate screen, restored on drop (including panics)
+struct Screen;
+
+impl Screen {
+    fn enter(out: &mut Stdout) -> Result<Screen, Box<dyn Error>> {
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// q, Esc or Ctrl-C
+/// Raw mode turns Ctrl-C into a key press instead of a signal
+fn quit_requested() -> Result<bool, Box<dyn Error>> {
+    while event::poll(Duration::from_millis(0))? {
+        if let Event::Key(KeyEvent { code, modifiers }) = event::read()? {
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(true),
+                _ => (),
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+fn draw(out: &mut Stdout, lines: &[String]) -> Result<(), Box<dyn Error>> {
+    for (row, line) in lines.iter().enumerate() {
+        queue!(out, MoveTo(0, row as u16), Print(line), Clear(ClearType::UntilNewLine))?;
+    }
+    queue!(out, MoveTo(0, lines.len() as u16), Clear(ClearType::FromCursorDown))?;
+    out.flush()?;
+
+    Ok(())
+}
+
+struct View {
+    device: DeviceId,
+    start: Instant,
+    report: Option<Report>,
+    actions: VecDeque<(Duration, Action)>,
+}
+
+struct ConfigView<'a> {
+    source: &'a str,
+    profile: &'a str,
+    layer: Option<&'a str>,
+    deadzone: Option<Deadzone>,
+}
+
+impl View {
+    fn push_actions(&mut self, actions: Vec<(Instant, Action)>) {
+        for (at, action) in actions {
+            self.actions.push_back((at.duration_since(self.start), action));
+        }
+        while self.actions.len() > ACTION_LINES {
+            self.actions.pop_front();
+        }
+    }
+
+    fn lines(&self, config: Option<ConfigView>) -> Vec<String> {
+        let mut info = vec![
+            "rattacker monitor, q to quit".to_string(),
+            format!("Device   {}", self.device),
+        ];
+
+        match config {
+            Some(ref c) => {
+                info.push(format!("Config   {}", c.source));
+                info.push(format!("Profile  '{}', layer {}", c.profile, c.layer.unwrap_or("base")));
+            }
+            None => info.push("Config   none, pass --config or --preset to see actions".to_string()),
+        }
+        info.push(String::new());
+
+        match self.report {
+            Some(ref r) => {
+                let bytes: Vec<String> = r.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                info.push(format!("Raw      {}", bytes.join(" ")));
+                info.push(format!("Packet   x {:#04x}  y {:#04x}  z {:#04x}",
+                    r.packet.x_axis, r.packet.y_axis, r.packet.z_axis));
+                info.push(format!("State    x {:+.2}  y {:+.2}  z {:+.2}",
+                    r.state.x_axis(), r.state.y_axis(), r.state.z_axis()));
+            }
+            None => info.push("Waiting for the first report to zero the stick...".to_string()),
+        }
+        info.push(String::new());
+
+        info.push("Buttons".to_string());
+        let pressed = self.report.as_ref().map(|r| *r.state.buttons()).unwrap_or([false; BUTTON_LEN]);
+        for pair in (0..BUTTON_LEN).collect::<Vec<_>>().chunks(2) {
+            let cells: Vec<String> = pair.iter()
+                .map(|i| format!("[{}] {:22}", if pressed[*i] { "x" } else { " " }, Button(*i).name()))
+                .collect();
+            info.push(format!("  {}", cells.join("")));
+        }
+        info.push(String::new());
+
+        if config.is_some() {
+            info.push("Actions (recorded, not sent)".to_string());
+            for (at, action) in self.actions.iter() {
+                info.push(format!("  {:>9.3}s  {}", at.as_secs_f32(), action));
+            }
+        }
+
+        let state = self.report.as_ref().map(|r| &r.state);
+        let plot = plot(state, config.and_then(|c| c.deadzone));
+
+        // Text on the left, plot on the right
+        let rows = info.len().max(plot.len());
+        (0..rows)
+            .map(|i| {
+                let left = info.get(i).map(|s| s.as_str()).unwrap_or("");
+                let right = plot.get(i).map(|s| s.as_str()).unwrap_or("");
+                format!("{:width$}{}", left, right, width = INFO_WIDTH)
+            })
+            .collect()
+    }
+}
+
+/// The stick's position as `@`, forward is up
+/// `.` marks the deadzone
+fn plot(state: Option<&State>, deadzone: Option<Deadzone>) -> Vec<String> {
+    let to_x = |col: usize| col as f32 / (PLOT_WIDTH - 1) as f32 * 2.0 - 1.0;
+    let to_y = |row: usize| 1.0 - row as f32 / (PLOT_HEIGHT - 1) as f32 * 2.0;
+
+    let mut grid = vec![vec![' '; PLOT_WIDTH]; PLOT_HEIGHT];
+    for (row, line) in grid.iter_mut().enumerate() {
+        for (col, c) in line.iter_mut().enumerate() {
+            let (x, y) = (to_x(col), to_y(row));
+            let in_deadzone = match deadzone {
+                Some(Deadzone::Axes(dx, dy)) => x.abs() < dx && y.abs() < dy,
+                Some(Deadzone::Radial(r)) => x.hypot(y) < r,
+                None => false,
+            };
+            let center_row = row == PLOT_HEIGHT / 2;
+            let center_col = col == PLOT_WIDTH / 2;
+
+            *c = match (center_row, center_col) {
+                (true, true) => '+',
+                (true, false) => '-',
+                (false, true) => '|',
+                _ if in_deadzone => '.',
+                _ => ' ',
+            };
+        }
+    }
+
+    if let Some(s) = state {
+        let col = ((s.x_axis().clamp(-1.0, 1.0) + 1.0) / 2.0 * (PLOT_WIDTH - 1) as f32).round() as usize;
+        let row = ((1.0 - s.y_axis().clamp(-1.0, 1.0)) / 2.0 * (PLOT_HEIGHT - 1) as f32).round() as usize;
+        grid[row][col] = '@';
+    }
+
+    let border = format!("+{}+", "-".repeat(PLOT_WIDTH));
+    let mut lines = vec![border.clone()];
+    lines.extend(grid.into_iter().map(|line| format!("|{}|", line.into_iter().collect::<String>())));
+    lines.push(border);
+    lines
+}
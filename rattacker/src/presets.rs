@@ -0,0 +1,119 @@
+use serde_json::Value;
+
+/// A config compiled into the binary
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    text: &'static str,
+}
+
+/// Starting points that work without the repo checked out
+/// Export one with `rattacker preset NAME OUTPUT` to customize it
+pub const PRESETS: [Preset; 5] = [
+    Preset {
+        name: "wasd",
+        description: "Stick presses WASD, for games (DirectX mode)",
+        text: include_str!("../presets/wasd.json"),
+    },
+    Preset {
+        name: "arrows",
+        description: "Stick presses the arrow keys, for menus and desktop use",
+        text: include_str!("../presets/arrows.json"),
+    },
+    Preset {
+        name: "mouse-linear",
+        description: "Stick moves the mouse, faster the further it is pushed",
+        text: include_str!("../presets/mouse-linear.json"),
+    },
+    Preset {
+        name: "mouse-logistic",
+        description: "Stick moves the mouse, precise near the center and fast at the edge",
+        text: include_str!("../presets/mouse-logistic.json"),
+    },
+    Preset {
+        name: "flight",
+        description: "Stick presses the arrow keys for pitch and roll, small deadzone (DirectX mode)",
+        text: include_str!("../presets/flight.json"),
+    },
+];
+
+impl Preset {
+    pub fn find(name: &str) -> Result<&'static Preset, String> {
+        PRESETS.iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| format!(
+                "There is no preset named '{}' (expected one of {})",
+                name,
+                PRESETS.iter().map(|p| p.name).collect::<Vec<_>>().join(", ")
+            ))
+    }
+
+    /// Parsed the same way as a config file
+    pub fn value(&self) -> Result<Value, String> {
+        serde_json::from_str(self.text)
+            .map_err(|e| format!("Could not read preset '{}': {}", self.name, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::config::ProfileSet;
+    use crate::dispatch::{Action, Manager, State};
+    use crate::raw_input::Packet;
+    use crate::record::RecordDispatch;
+    use crate::validate;
+
+    #[test]
+    fn presets_load() {
+        for preset in PRESETS.iter() {
+            let value = preset.value().unwrap();
+            let profiles = ProfileSet::from_value(value)
+                .unwrap_or_else(|e| panic!("{}: {}", preset.name, e));
+            assert_eq!(profiles.profiles.len(), 1, "{}", preset.name);
+
+            let config = profiles.profiles[0].config.clone();
+            if let Err(e) = validate::validate_config(&config) {
+                panic!("{}: {}", preset.name, e);
+            }
+
+            let recorder = Rc::new(RecordDispatch::new());
+            let mut manager = Manager::with_dispatcher(config, recorder.clone());
+            let state = |pressed: bool, x_axis: u8, y_axis: u8| State::from_packet([0x80, 0x80], Packet {
+                buttons: [pressed; 11],
+                x_axis,
+                y_axis,
+                z_axis: 0x80,
+            });
+            manager.step(state(false, 0x80, 0x80));
+            manager.step(state(true, 0xff, 0x00));
+            let pressed = recorder.take();
+            assert!(!pressed.is_empty(), "{}: pressing every button sent nothing", preset.name);
+
+            manager.release_all();
+            let mut held = BTreeSet::new();
+            for (_, action) in pressed.into_iter().chain(recorder.take()) {
+                match action {
+                    Action::KeyDown(k) => { held.insert(k); }
+                    Action::KeyUp(k) => { held.remove(&k); }
+                    _ => (),
+                }
+            }
+            assert!(held.is_empty(), "{}: still held after release_all: {:?}", preset.name, held);
+        }
+    }
+
+    #[test]
+    fn preset_names_are_unique() {
+        for (i, preset) in PRESETS.iter().enumerate() {
+            assert!(
+                PRESETS[..i].iter().all(|p| p.name != preset.name),
+                "two presets are named '{}'",
+                preset.name
+            );
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use super::config::{ProfileSet, SwitchTarget};
+use super::dispatch::State;
+
+struct Combo {
+    buttons: Vec<usize>,
+    target: Target,
+    held: bool,
+}
+
+enum Target {
+    Next,
+    Index(usize),
+}
+
+/// Watches for the button combinations of a ProfileSet
+pub struct Switcher {
+    combos: Vec<Combo>,
+    profile_count: usize,
+}
+
+impl Switcher {
+    /// Expects a set that passed validate_profiles, e.g. no switch without buttons
+    pub fn new(set: &ProfileSet) -> Result<Self, String> {
+        let mut combos = Vec::new();
+        for switch in set.switches.iter() {
+            let buttons = switch.buttons.iter().map(|b| b.0).collect();
+
+            let target = match switch.target {
+                SwitchTarget::Next => Target::Next,
+                SwitchTarget::Profile(ref name) => {
+                    let index = set.profiles
+                        .iter()
+                        .position(|p| p.name == *name)
+                        .ok_or_else(|| format!("Profile switch to unknown profile '{}'", name))?;
+                    Target::Index(index)
+                }
+            };
+
+            combos.push(Combo {
+                buttons,
+                target,
+                held: false,
+            });
+        }
+
+        Ok(Switcher {
+            combos,
+            profile_count: set.profiles.len(),
+        })
+    }
+
+    /// Returns the profile to switch to when a combination is completed
+    pub fn check(&mut self, s: &State, current: usize) -> Option<usize> {
+        let mut switch_to = None;
+        for combo in self.combos.iter_mut() {
+            let held = combo.buttons.iter().all(|b| s.buttons()[*b]);
+            if held && !combo.held && switch_to.is_none() {
+                switch_to = match combo.target {
+                    Target::Next => Some((current + 1) % self.profile_count),
+                    Target::Index(i) => Some(i),
+                };
+            }
+            combo.held = held;
+        }
+
+        switch_to
+    }
+}
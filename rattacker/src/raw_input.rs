@@ -1,5 +1,6 @@
 use std::fmt;
 
+/// One HID report of the Attack3, as sent
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Packet {
     pub buttons: [bool; 11],
@@ -23,8 +24,11 @@ impl fmt::Display for Packet {
 }
 
 impl Packet {
+    /// Decodes a report: x, y and z bytes, then two bytes of button bits
     pub fn parse(b: &[u8]) -> Result<Packet, String> {
-        assert!(b.len() == 5);
+        if b.len() != 5 {
+            return Err(format!("Expected a 5 byte packet, got {} bytes", b.len()));
+        }
 
         let mut packet = Packet {
             buttons: [false; 11],
@@ -0,0 +1,96 @@
+use std::cell::RefCell;
+use std::time::Instant;
+
+use super::config::{Config, Key, Wheel};
+use super::dispatch::{Action, Dispatcher};
+
+/// Records actions instead of sending them
+#[derive(Default)]
+pub struct RecordDispatch {
+    actions: RefCell<Vec<(Instant, Action)>>,
+}
+
+impl RecordDispatch {
+    pub fn new() -> Self {
+        RecordDispatch {
+            actions: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Every action recorded since the last call
+    pub fn take(&self) -> Vec<(Instant, Action)> {
+        self.actions.replace(Vec::new())
+    }
+
+    fn record(&self, action: Action) {
+        self.actions.borrow_mut().push((Instant::now(), action));
+    }
+}
+
+impl Dispatcher for RecordDispatch {
+    fn from_cfg(_cfg: &Config) -> Self {
+        RecordDispatch::new()
+    }
+
+    fn key_up(&self, k: Key) {
+        self.record(Action::KeyUp(k));
+    }
+
+    fn key_down(&self, k: Key) {
+        self.record(Action::KeyDown(k));
+    }
+
+    fn rel_mouse(&self, dx: i32, dy: i32) {
+        self.record(Action::RelMouse { dx, dy });
+    }
+
+    fn scroll(&self, wheel: Wheel, delta: i32) {
+        self.record(Action::Scroll { wheel, delta });
+    }
+}
+
+/// Prints actions instead of sending them, timed from when it was made
+/// Shared by every Manager of a run so the times keep counting across reloads
+pub struct LogDispatch {
+    start: Instant,
+}
+
+impl LogDispatch {
+    pub fn new() -> Self {
+        LogDispatch {
+            start: Instant::now(),
+        }
+    }
+
+    fn log(&self, action: Action) {
+        println!("{:>9.3}s  {}", self.start.elapsed().as_secs_f32(), action);
+    }
+}
+
+impl Default for LogDispatch {
+    fn default() -> Self {
+        LogDispatch::new()
+    }
+}
+
+impl Dispatcher for LogDispatch {
+    fn from_cfg(_cfg: &Config) -> Self {
+        LogDispatch::new()
+    }
+
+    fn key_up(&self, k: Key) {
+        self.log(Action::KeyUp(k));
+    }
+
+    fn key_down(&self, k: Key) {
+        self.log(Action::KeyDown(k));
+    }
+
+    fn rel_mouse(&self, dx: i32, dy: i32) {
+        self.log(Action::RelMouse { dx, dy });
+    }
+
+    fn scroll(&self, wheel: Wheel, delta: i32) {
+        self.log(Action::Scroll { wheel, delta });
+    }
+}
@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the config files' modification times are checked
+/// The main loop spins far faster than anyone can save a file
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls a config file, and the files it extends, for changes
+pub struct ConfigWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_check: Instant,
+}
+
+impl ConfigWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = modified(&path);
+                (path, modified)
+            })
+            .collect();
+
+        ConfigWatcher {
+            files,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Returns true once for every change to any of the files
+    pub fn poll(&mut self) -> bool {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        let mut changed = false;
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+            // A missing file is usually an editor mid-save, wait for it to come back
+            if modified.is_none() || modified == *last_modified {
+                continue;
+            }
+
+            *last_modified = modified;
+            changed = true;
+        }
+
+        changed
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+}
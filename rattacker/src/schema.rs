@@ -0,0 +1,94 @@
+use schemars::JsonSchema;
+use schemars::schema::RootSchema;
+
+use super::config::{Config, ProfileSet};
+
+/// Either layout a config file can have
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[schemars(title = "rattacker config")]
+enum ConfigFile {
+    Profiles(ProfileSet),
+    Single(Box<Config>),
+}
+
+/// JSON Schema for config files, derived from the types in config.rs
+/// Doc comments become descriptions
+pub fn config_schema() -> RootSchema {
+    schemars::schema_for!(ConfigFile)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use jsonschema::JSONSchema;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::includes;
+
+    /// Written by `cargo run -- schema config.schema.json`
+    const SNAPSHOT: &str = "config.schema.json";
+
+    fn schema() -> Value {
+        serde_json::to_value(config_schema()).unwrap()
+    }
+
+    /// Every config in joy-config/ and presets/, not the fragments they extend
+    fn config_files() -> Vec<PathBuf> {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut files = Vec::new();
+        for dir in ["joy-config", "presets"].iter() {
+            for entry in fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    fn check(schema: &JSONSchema, what: &str, value: &Value) {
+        if let Err(errors) = schema.validate(value) {
+            let errors: Vec<String> = errors
+                .map(|e| format!("{} at '{}'", e, e.instance_path))
+                .collect();
+            panic!("{} does not match the schema:\n  {}", what, errors.join("\n  "));
+        }
+    }
+
+    #[test]
+    fn configs_match_schema() {
+        let schema_value = schema();
+        let schema = JSONSchema::compile(&schema_value).unwrap();
+
+        let files = config_files();
+        assert!(!files.is_empty());
+        for path in files.iter() {
+            let name = path.display().to_string();
+            let resolved = includes::resolve(&name).unwrap().value;
+            check(&schema, &name, &resolved);
+
+            let profiles = ProfileSet::from_value(resolved)
+                .unwrap_or_else(|e| panic!("{}: {}", name, e));
+            let serialized = serde_json::to_value(&profiles).unwrap();
+            check(&schema, &format!("{} as serialized", name), &serialized);
+        }
+    }
+
+    #[test]
+    fn schema_matches_snapshot() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT);
+        let snapshot: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(
+            snapshot == schema(),
+            "The config types changed, check the schema with `cargo run -- schema {}` and commit it",
+            SNAPSHOT
+        );
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use super::config::*;
+
+/// A single semantic problem in a config
+/// `path` follows the JSON layout, e.g. `joystick.Mouse.y_axis.dots_per_pixel.Linear.m`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Every problem found in a config
+pub struct ValidationError(pub Vec<Problem>);
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid config ({} problem(s))", self.0.len())?;
+        for p in self.0.iter() {
+            write!(f, "\n  {}", p)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Reads the same as Display, e.g. when unwrapped
+impl fmt::Debug for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Checks the things deserialization cannot, e.g. values that would
+///   divide by zero or a button switching two layers
+pub fn validate_profiles(set: &ProfileSet) -> Result<(), ValidationError> {
+    let mut v = Validator::new();
+
+    let mut names: HashMap<&str, usize> = HashMap::new();
+    for (i, profile) in set.profiles.iter().enumerate() {
+        if let Some(first) = names.insert(&profile.name, i) {
+            v.problem(format!("profiles[{}].name", i),
+                format!("'{}' is already the name of profiles[{}]", profile.name, first));
+        }
+        v.config(&format!("profiles[{}].config", i), &profile.config);
+    }
+
+    for (i, switch) in set.switches.iter().enumerate() {
+        let path = format!("switches[{}]", i);
+        if switch.buttons.is_empty() {
+            v.problem(format!("{}.buttons", path), "needs at least one button");
+        }
+        if let SwitchTarget::Profile(ref name) = switch.target {
+            if !names.contains_key(name.as_str()) {
+                v.problem(format!("{}.target.Profile", path),
+                    format!("there is no profile named '{}'", name));
+            }
+        }
+    }
+
+    v.finish()
+}
+
+/// Same as validate_profiles() for a file holding a single Config
+pub fn validate_config(cfg: &Config) -> Result<(), ValidationError> {
+    let mut v = Validator::new();
+    v.config("", cfg);
+    v.finish()
+}
+
+struct Validator {
+    problems: Vec<Problem>,
+}
+
+impl Validator {
+    fn new() -> Self {
+        Validator {
+            problems: Vec::new(),
+        }
+    }
+
+    fn finish(self) -> Result<(), ValidationError> {
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError(self.problems))
+        }
+    }
+
+    fn problem<P: Into<String>, M: Into<String>>(&mut self, path: P, message: M) {
+        self.problems.push(Problem {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn config(&mut self, prefix: &str, cfg: &Config) {
+        self.bindings(&join(prefix, "buttons"), &cfg.buttons);
+        if let Some(ref joystick) = cfg.joystick {
+            self.joystick(&join(prefix, "joystick"), joystick);
+        }
+        if let Some(ThrottleConfig::Scroll { ref z_axis, .. }) = cfg.throttle {
+            self.scroll_axis(&join(prefix, "throttle.Scroll.z_axis"), z_axis);
+        }
+        for (i, gesture) in cfg.gestures.iter().enumerate() {
+            self.gesture(&join(prefix, &format!("gestures[{}]", i)), gesture);
+        }
+
+        let mut layer_buttons: HashMap<Button, usize> = HashMap::new();
+        for (i, layer) in cfg.layers.iter().enumerate() {
+            let path = join(prefix, &format!("layers[{}]", i));
+            if let Some(first) = layer_buttons.insert(layer.button, i) {
+                self.problem(format!("{}.button", path),
+                    format!("{} already switches layers[{}]", layer.button, first));
+            }
+
+            self.bindings(&format!("{}.buttons", path), &layer.buttons);
+            if let Some(ref joystick) = layer.joystick {
+                self.joystick(&format!("{}.joystick", path), joystick);
+            }
+        }
+    }
+
+    // NOTE: a key may be bound to several buttons, it stays down until all of them let go
+    //   (or presses again per Config::key_policies)
+    fn bindings(&mut self, path: &str, buttons: &[Option<Binding>; BUTTON_LEN]) {
+        for (i, b) in buttons.iter().enumerate() {
+            if let Some(b) = b {
+                self.binding(&format!("{}.{}", path, Button(i).name()), b);
+            }
+        }
+    }
+
+    fn binding(&mut self, path: &str, b: &Binding) {
+        if let Binding::Action(ButtonAction::Turbo(turbo)) = b {
+            let path = format!("{}.Turbo", path);
+            if !positive(turbo.rate) {
+                self.problem(format!("{}.rate", path), "must be a positive number of presses per second");
+            }
+            if !(0.0..=1.0).contains(&turbo.duty_cycle) {
+                self.problem(format!("{}.duty_cycle", path), "must be in range [0.0, 1.0]");
+            }
+        }
+
+        if let Binding::Action(ButtonAction::Scroll(scroll)) = b {
+            let path = format!("{}.Scroll", path);
+            if scroll.notches == 0.0 || !scroll.notches.is_finite() {
+                self.problem(format!("{}.notches", path), "must be a non-zero number");
+            }
+            if let Some(repeat) = scroll.repeat {
+                if !positive(repeat) {
+                    self.problem(format!("{}.repeat", path), "must be a positive number of scrolls per second");
+                }
+            }
+        }
+    }
+
+    fn joystick(&mut self, path: &str, joystick: &JoystickConfig) {
+        match joystick {
+            JoystickConfig::Keys { x_axis, y_axis } => {
+                let path = format!("{}.Keys", path);
+                self.key_axis(&format!("{}.x_axis", path), x_axis);
+                self.key_axis(&format!("{}.y_axis", path), y_axis);
+            }
+
+            JoystickConfig::Mouse { x_axis, y_axis } => {
+                let path = format!("{}.Mouse", path);
+                self.mouse_axis(&format!("{}.x_axis", path), x_axis);
+                self.mouse_axis(&format!("{}.y_axis", path), y_axis);
+            }
+
+            JoystickConfig::Scroll { x_axis, y_axis } => {
+                let path = format!("{}.Scroll", path);
+                self.scroll_axis(&format!("{}.x_axis", path), x_axis);
+                self.scroll_axis(&format!("{}.y_axis", path), y_axis);
+            }
+
+            JoystickConfig::Directions(directions) => {
+                let path = format!("{}.Directions", path);
+                self.deadzone(&format!("{}.deadzone", path), directions.deadzone);
+                if directions.keys.values().all(|keys| keys.is_empty()) {
+                    self.problem(format!("{}.keys", path), "needs keys for at least one direction");
+                }
+                if let Some(width) = directions.width {
+                    if !(width > 0.0 && width <= 360.0) {
+                        self.problem(format!("{}.width", path), "must be in range (0.0, 360.0] degrees");
+                    }
+                }
+                if !non_negative(directions.overlap) {
+                    self.problem(format!("{}.overlap", path), "must not be negative");
+                }
+            }
+        }
+    }
+
+    fn gesture(&mut self, path: &str, gesture: &GestureConfig) {
+        self.binding(&format!("{}.action", path), &gesture.action);
+        if !positive(gesture.window) {
+            self.problem(format!("{}.window", path), "must be a positive number of seconds");
+        }
+        if !(gesture.threshold > 0.0 && gesture.threshold <= 1.0) {
+            self.problem(format!("{}.threshold", path), "must be in range (0.0, 1.0]");
+        }
+        if let Gesture::Flick(_) = gesture.gesture {
+            if !(0.0..gesture.threshold).contains(&gesture.center) {
+                self.problem(format!("{}.center", path),
+                    format!("must be in range [0.0, {}), inside the threshold", gesture.threshold));
+            }
+        }
+        if !non_negative(gesture.hold) {
+            self.problem(format!("{}.hold", path), "must not be a negative number of seconds");
+        }
+    }
+
+    fn key_axis(&mut self, path: &str, axis: &AxisKeyConfig) {
+        self.deadzone(&format!("{}.deadzone", path), axis.deadzone);
+        if let Some(pwm) = axis.pwm {
+            if !positive(pwm.period) {
+                self.problem(format!("{}.pwm.period", path), "must be a positive number of seconds");
+            }
+            if !axis.stages.is_empty() {
+                self.problem(format!("{}.stages", path), "cannot be combined with pwm");
+            }
+        }
+
+        let mut below = axis.deadzone;
+        for (i, stage) in axis.stages.iter().enumerate() {
+            let path = format!("{}.stages[{}]", path, i);
+            if !(stage.threshold > below && stage.threshold < 1.0) {
+                self.problem(format!("{}.threshold", path),
+                    format!("must be in range ({}, 1.0), past the deadzone and the stage before", below));
+            }
+            if stage.positive.is_none() && stage.negative.is_none() {
+                self.problem(path.clone(), "needs positive or negative keys, otherwise the stage changes nothing");
+            }
+            for (side, keys) in [("positive", &stage.positive), ("negative", &stage.negative)].iter() {
+                if matches!(keys, Some(keys) if keys.is_empty()) {
+                    self.problem(format!("{}.{}", path, side), "needs at least one key");
+                }
+            }
+            below = stage.threshold;
+        }
+    }
+
+    fn scroll_axis(&mut self, path: &str, axis: &AxisScrollConfig) {
+        self.deadzone(&format!("{}.deadzone", path), axis.deadzone);
+        if !positive(axis.rate) {
+            self.problem(format!("{}.rate", path), "must be a positive number of notches per second");
+        }
+        if !positive(axis.exponent) {
+            self.problem(format!("{}.exponent", path), "must be positive");
+        }
+    }
+
+    fn deadzone(&mut self, path: &str, deadzone: f32) {
+        if !(0.0..1.0).contains(&deadzone) {
+            self.problem(path, "must be in range [0.0, 1.0)");
+        }
+    }
+
+    fn mouse_axis(&mut self, path: &str, axis: &AxisMouseConfig) {
+        self.deadzone(&format!("{}.deadzone", path), axis.deadzone);
+        if axis.dpi == 0.0 || !axis.dpi.is_finite() {
+            self.problem(format!("{}.dpi", path), "must be a non-zero number");
+        }
+
+        let path = format!("{}.dots_per_pixel", path);
+        match axis.dots_per_pixel {
+            MouseMode::Constant(c) => {
+                if c == 0.0 || !c.is_finite() {
+                    self.problem(format!("{}.Constant", path), "must be a non-zero number");
+                }
+            }
+
+            MouseMode::Linear { m, bias } => {
+                let path = format!("{}.Linear", path);
+                if !m.is_finite() {
+                    self.problem(format!("{}.m", path), "must be a number");
+                }
+                // g(1.0) = bias * sign(m)
+                if !positive(bias) {
+                    self.problem(format!("{}.bias", path),
+                        "must be positive, otherwise dots_per_pixel reaches zero before full deflection");
+                }
+            }
+
+            MouseMode::Logistic { target, min, a, b, c, d, h } => {
+                let path = format!("{}.Logistic", path);
+                if target == 0.0 || !target.is_finite() {
+                    self.problem(format!("{}.target", path), "must be a non-zero number");
+                }
+                if !non_negative(min) {
+                    self.problem(format!("{}.min", path), "must not be negative");
+                }
+                if !positive(b) {
+                    self.problem(format!("{}.b", path), "must be positive");
+                }
+                for (name, value) in [("a", a), ("c", c), ("d", d), ("h", h)].iter() {
+                    if !value.is_finite() {
+                        self.problem(format!("{}.{}", path, name), "must be a number");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A number greater than zero, false for NaN and infinity
+fn positive(x: f32) -> bool {
+    x.is_finite() && x > 0.0
+}
+
+/// Zero or a number greater than it, false for NaN and infinity
+fn non_negative(x: f32) -> bool {
+    x.is_finite() && x >= 0.0
+}
+
+fn join(prefix: &str, field: &str) -> String {
+    if prefix.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", prefix, field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn key_bound_to_several_buttons() {
+        let cfg: Config = serde_json::from_value(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": { "trigger": "W", "thumb_up": "W" },
+            "key_policies": { "W": "Retrigger" }
+        })).unwrap();
+        assert!(validate_config(&cfg).is_ok());
+    }
+
+    fn problem(path: &str, message: &str) -> Problem {
+        Problem {
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn config_problems(cfg: serde_json::Value) -> Vec<Problem> {
+        let cfg: Config = serde_json::from_value(cfg).unwrap();
+        validate_config(&cfg).unwrap_err().0
+    }
+
+    #[test]
+    fn turbo_rate_zero() {
+        let problems = config_problems(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": { "trigger": { "Turbo": { "key": "E", "rate": 0.0, "duty_cycle": 0.5 } } }
+        }));
+        assert_eq!(problems, vec![
+            problem("buttons.trigger.Turbo.rate", "must be a positive number of presses per second"),
+        ]);
+    }
+
+    #[test]
+    fn deadzone_one() {
+        let problems = config_problems(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": {},
+            "joystick": {
+                "Keys": {
+                    "x_axis": { "positive": "D", "negative": "A", "deadzone": 1.0 },
+                    "y_axis": { "positive": "W", "negative": "S", "deadzone": 0.2 }
+                }
+            }
+        }));
+        assert_eq!(problems, vec![
+            problem("joystick.Keys.x_axis.deadzone", "must be in range [0.0, 1.0)"),
+        ]);
+    }
+
+    #[test]
+    fn stages_not_increasing() {
+        let problems = config_problems(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": {},
+            "joystick": {
+                "Keys": {
+                    "x_axis": { "positive": "D", "negative": "A", "deadzone": 0.2 },
+                    "y_axis": {
+                        "positive": "W",
+                        "negative": "S",
+                        "deadzone": 0.2,
+                        "stages": [
+                            { "threshold": 0.6, "positive": ["Shift"] },
+                            { "threshold": 0.4, "positive": ["Ctrl"] }
+                        ]
+                    }
+                }
+            }
+        }));
+        assert_eq!(problems, vec![
+            problem("joystick.Keys.y_axis.stages[1].threshold",
+                "must be in range (0.6, 1.0), past the deadzone and the stage before"),
+        ]);
+    }
+
+    #[test]
+    fn linear_bias_not_positive() {
+        let problems = config_problems(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": {},
+            "joystick": {
+                "Mouse": {
+                    "x_axis": { "dots_per_pixel": { "Linear": { "m": -1.0, "bias": 0.0 } }, "dpi": 1.0, "deadzone": 0.1 },
+                    "y_axis": { "dots_per_pixel": { "Constant": 2.0 }, "dpi": 1.0, "deadzone": 0.1 }
+                }
+            }
+        }));
+        assert_eq!(problems, vec![
+            problem("joystick.Mouse.x_axis.dots_per_pixel.Linear.bias",
+                "must be positive, otherwise dots_per_pixel reaches zero before full deflection"),
+        ]);
+    }
+
+    #[test]
+    fn duplicate_layer_button() {
+        let layer = |name: &str, key: &str| json!({
+            "name": name,
+            "button": "base_right_back",
+            "activation": "Hold",
+            "buttons": { "trigger": key }
+        });
+        let problems = config_problems(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": {},
+            "layers": [
+                layer("first", "E"),
+                layer("second", "Q"),
+                {
+                    "name": "third",
+                    "button": "base_left_front",
+                    "activation": "Toggle",
+                    "buttons": { "trigger": { "Turbo": { "key": "E", "rate": -1.0, "duty_cycle": 0.5 } } }
+                }
+            ]
+        }));
+        assert_eq!(problems, vec![
+            problem("layers[1].button", "base_right_back (10) already switches layers[0]"),
+            problem("layers[2].buttons.trigger.Turbo.rate", "must be a positive number of presses per second"),
+        ]);
+    }
+
+    #[test]
+    fn profile_problems() {
+        let set: ProfileSet = serde_json::from_value(json!({
+            "version": 2,
+            "profiles": [
+                { "name": "main", "config": { "version": 2, "mode": "Normal", "buttons": {} } },
+                {
+                    "name": "fast",
+                    "config": {
+                        "version": 2,
+                        "mode": "Normal",
+                        "buttons": { "thumb_up": { "Turbo": { "key": "E", "rate": 10.0, "duty_cycle": 1.5 } } }
+                    }
+                }
+            ],
+            "switches": [
+                { "buttons": ["base_left_front"], "target": { "Profile": "slow" } }
+            ]
+        })).unwrap();
+        assert_eq!(validate_profiles(&set).unwrap_err().0, vec![
+            problem("profiles[1].config.buttons.thumb_up.Turbo.duty_cycle", "must be in range [0.0, 1.0]"),
+            problem("switches[0].target.Profile", "there is no profile named 'slow'"),
+        ]);
+    }
+
+    #[test]
+    fn stages_without_keys() {
+        let problems = config_problems(json!({
+            "version": 2,
+            "mode": "Normal",
+            "buttons": {},
+            "joystick": {
+                "Keys": {
+                    "x_axis": { "positive": "D", "negative": "A", "deadzone": 0.2 },
+                    "y_axis": {
+                        "positive": "W",
+                        "negative": "S",
+                        "deadzone": 0.2,
+                        "stages": [
+                            { "threshold": 0.5 },
+                            { "threshold": 0.8, "positive": [], "negative": ["Ctrl"] }
+                        ]
+                    }
+                }
+            }
+        }));
+        assert_eq!(problems, vec![
+            problem("joystick.Keys.y_axis.stages[0]", "needs positive or negative keys, otherwise the stage changes nothing"),
+            problem("joystick.Keys.y_axis.stages[1].positive", "needs at least one key"),
+        ]);
+    }
+}
@@ -1,8 +1,16 @@
 use std::mem;
 use std::collections::HashMap;
 
-use super::dispatch::Dispatcher;
-use super::config::{ Config, Key, Mode};
+use super::dispatch::{Action, Dispatcher};
+use super::config::{
+    Binding,
+    Config,
+    JoystickConfig,
+    Key,
+    Mode,
+    Wheel,
+    BUTTON_LEN,
+};
 
 use bindings::windows::win32::keyboard_and_mouse_input::{
     SendInput,
@@ -28,50 +36,23 @@ impl WinDispatch {
             return;
         }
 
-        // TODO: check if mouse button handling needs to be different across modes
-        if k.is_mouse() {
-            self.key_down.insert(k.clone(),
-                Input::new_mouse_key(k.clone(), false));
-            self.key_up.insert(k.clone(),
-                Input::new_mouse_key(k.clone(), true));
-            return;
-        }
-
-        match mode {
-            Mode::DirectX => {
-                self.key_down.insert(k.clone(),
-                Input::new_directx_key(k.clone(), false));
-                self.key_up.insert(k.clone(),
-                Input::new_directx_key(k.clone(), true));
-            }
-
-            Mode::Normal => {
-                self.key_down.insert(k.clone(),
-                Input::new_normal_key(k.clone(), false));
-                self.key_up.insert(k.clone(),
-                Input::new_normal_key(k.clone(), true));
-            }
-        }
+        self.key_down.insert(k.clone(), Input::new_key(k.clone(), mode, false));
+        self.key_up.insert(k.clone(), Input::new_key(k.clone(), mode, true));
     }
-}
-
-impl Dispatcher for WinDispatch {
-    fn from_cfg(cfg: &Config) -> Self {
-        use super::config::*;
-
-        let mut disp = WinDispatch {
-            mode: cfg.mode.clone(),
-            key_down: HashMap::new(),
-            key_up: HashMap::new(),
-        };
 
-        for k in cfg.buttons.iter() {
-            if let Some(k) = k {
-                disp.cache_key(k, cfg.mode);
+    fn cache_bindings(
+        &mut self,
+        buttons: &[Option<Binding>; BUTTON_LEN],
+        joystick: &Option<JoystickConfig>,
+        mode: Mode,
+    ) {
+        for b in buttons.iter() {
+            if let Some(k) = b.and_then(|b| b.key()) {
+                self.cache_key(&k, mode);
             }
         }
 
-        match cfg.joystick {
+        match joystick {
             Some(JoystickConfig::Keys {
                 x_axis,
                 y_axis
@@ -85,22 +66,55 @@ impl Dispatcher for WinDispatch {
                 // dbg!(joy_keys);
 
                 for k in joy_keys.iter() {
-                    disp.cache_key(k, cfg.mode);
+                    self.cache_key(k, mode);
                 }
 
-                disp
+                let stage_keys = x_axis.stages.iter()
+                    .chain(y_axis.stages.iter())
+                    .flat_map(|s| s.positive.iter().chain(s.negative.iter()))
+                    .flatten();
+                for k in stage_keys {
+                    self.cache_key(k, mode);
+                }
+            }
+
+            Some(JoystickConfig::Directions(directions)) => {
+                for k in directions.keys.values().flatten() {
+                    self.cache_key(k, mode);
+                }
             }
 
             Some(JoystickConfig::Mouse {
                 ..
+            }) | Some(JoystickConfig::Scroll {
+                ..
             }) => {
                 // Do no pre-caching for now
-                disp
             }
 
-            None => disp,
+            None => (),
         }
     }
+}
+
+impl Dispatcher for WinDispatch {
+    fn from_cfg(cfg: &Config) -> Self {
+        let mut disp = WinDispatch {
+            mode: cfg.mode.clone(),
+            key_down: HashMap::new(),
+            key_up: HashMap::new(),
+        };
+
+        disp.cache_bindings(&cfg.buttons, &cfg.joystick, cfg.mode);
+        for layer in cfg.layers.iter() {
+            disp.cache_bindings(&layer.buttons, &layer.joystick, cfg.mode);
+        }
+        for k in cfg.gestures.iter().filter_map(|g| g.action.key()) {
+            disp.cache_key(&k, cfg.mode);
+        }
+
+        disp
+    }
 
     fn key_up(&self, k: Key) {
         let input = self.key_up.get(&k).unwrap() as *const _ as *mut Input;
@@ -124,13 +138,12 @@ impl Dispatcher for WinDispatch {
         }
     }
 
-    fn rel_mouse_x(&self, r: i32) {
-        // println!("Attempting to send rel_x: {}", r);
+    fn rel_mouse(&self, dx: i32, dy: i32) {
         match self.mode {
             Mode::DirectX | Mode::Normal => {
                 unsafe {
                     // TODO: check if we need to extend the lifetime of input
-                    let input = Input::new_mouse_rel(r, 0);
+                    let input = Input::new_mouse_rel(dx, dy);
                     let input = &input as *const _;
                     SendInput(1, mem::transmute(input),
                     mem::size_of::<Input>() as i32);
@@ -139,18 +152,30 @@ impl Dispatcher for WinDispatch {
         }
     }
 
-    fn rel_mouse_y(&self, r: i32) {
-        // println!("Attempting to send rel_y: {}", r);
-        match self.mode {
-            Mode::DirectX | Mode::Normal => {
-                unsafe {
-                    // TODO: check if we need to extend the lifetime of input
-                    let input = Input::new_mouse_rel(0, r);
-                    let input = &input as *const _;
-                    SendInput(1, mem::transmute(input),
-                    mem::size_of::<Input>() as i32);
-                }
-            }
+    fn scroll(&self, wheel: Wheel, delta: i32) {
+        unsafe {
+            let input = Input::new_wheel(wheel, delta);
+            let input = &input as *const _;
+            SendInput(1, mem::transmute(input),
+            mem::size_of::<Input>() as i32);
+        }
+    }
+
+    /// One SendInput for every action, so no other input lands between them
+    fn send(&self, actions: &[Action]) {
+        let mut inputs: Vec<Input> = actions.iter()
+            .map(|action| match *action {
+                Action::KeyDown(k) => Input::new_key(k, self.mode, false),
+                Action::KeyUp(k) => Input::new_key(k, self.mode, true),
+                Action::RelMouse { dx, dy } => Input::new_mouse_rel(dx, dy),
+                Action::Scroll { wheel, delta } => Input::new_wheel(wheel, delta),
+            })
+            .collect();
+
+        unsafe {
+            SendInput(inputs.len() as u32,
+                      mem::transmute(inputs.as_mut_ptr()),     // TODO: remove when windows bindings can handle INPUT
+                      mem::size_of::<Input>() as i32);
         }
     }
 }
@@ -295,6 +320,8 @@ const MOUSEEVENTF_LEFTDOWN: u32 = 0x0002;
 const MOUSEEVENTF_LEFTUP: u32 = 0x0004;
 const MOUSEEVENTF_RIGHTDOWN: u32 = 0x0008;
 const MOUSEEVENTF_RIGHTUP: u32 = 0x0010;
+const MOUSEEVENTF_WHEEL: u32 = 0x0800;
+const MOUSEEVENTF_HWHEEL: u32 = 0x1000;
 
 const TAG_MOUSE: u32 = 0;
 const TAG_KEY: u32 = 1;
@@ -317,6 +344,19 @@ struct Input {
 
 impl Input {
 
+    /// Mouse buttons are the same in both modes
+    /// TODO: check if mouse button handling needs to be different across modes
+    fn new_key(k: Key, mode: Mode, up: bool) -> Self {
+        if k.is_mouse() {
+            return Input::new_mouse_key(k, up);
+        }
+
+        match mode {
+            Mode::DirectX => Input::new_directx_key(k, up),
+            Mode::Normal => Input::new_normal_key(k, up),
+        }
+    }
+
     fn new_mouse_key(k: Key, up: bool) -> Self {
         let event = match (k, up) {
             (Key::LMB, true) => MOUSEEVENTF_LEFTUP,
@@ -371,6 +411,26 @@ impl Input {
         }
     }
 
+    /// delta is in 1/120ths of a notch (WHEEL_DELTA), the sign is kept in mouse_data
+    fn new_wheel(wheel: Wheel, delta: i32) -> Self {
+        Input {
+            tag: TAG_MOUSE,
+            union: InputUnion {
+                mi: mem::ManuallyDrop::new(MOUSEINPUT {
+                    dx: 0,
+                    dy: 0,
+                    mouse_data: delta as u32,
+                    dw_flags: match wheel {
+                        Wheel::Vertical => MOUSEEVENTF_WHEEL,
+                        Wheel::Horizontal => MOUSEEVENTF_HWHEEL,
+                    },
+                    time: 0,
+                    dw_extra_info: unsafe { GetMessageExtraInfo() }.0 as usize,
+                })
+            }
+        }
+    }
+
     fn new_mouse_rel(x_amount: i32, y_amount: i32) -> Self {
         Input {
             tag: TAG_MOUSE,